@@ -1,22 +1,209 @@
-use crate::env::ParentEnv;
-use crate::env::{Env, EnvRef, EnvError};
+use crate::env::{Env, EnvRef};
+use crate::printer::{self, Style};
+use crate::span::Span;
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
 use std::fmt;
 use std::rc::Rc;
 
+pub use crate::number::Number;
+use crate::number::NumberError;
+
 #[derive(Debug)]
 pub enum ErrorKind {
-    ErrorGeneral(&'static str),
-    ErrorEval(&'static str),
-    ErrorUnknSym(&'static str),
+    UnboundSymbol(String),
+    // `got` is the type name of the value received, see ValType::type_name
+    TypeMismatch {
+        func: String,
+        expected: &'static str,
+        got: &'static str,
+    },
+    Arity {
+        func: String,
+        expected: String,
+        got: usize,
+        // Where the lambda was defined, None for builtins. Boxed to keep
+        // ASTError small, it's returned everywhere.
+        defined: Option<Box<Span>>,
+    },
+    DivisionByZero,
+    Overflow,
+    // Raised by the `error` builtin
+    UserError(Val),
+    // Misuse that is not about a single value, e.g. an index out of range
+    // or a malformed parameter list
+    Eval {
+        func: String,
+        msg: String,
+    },
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::UnboundSymbol(v) => write!(f, "unbound symbol: {}", v),
+            ErrorKind::TypeMismatch {
+                func,
+                expected,
+                got,
+            } => write!(f, "{} -- expected {}, got {}", func, expected, got),
+            ErrorKind::Arity {
+                func,
+                expected,
+                got,
+                ..
+            } => {
+                let noun = if expected.trim_start_matches("at least ") == "1" { "arg" } else { "args" };
+                write!(f, "{} -- expected {} {}, got {}", func, expected, noun, got)
+            }
+            ErrorKind::DivisionByZero => write!(f, "division by zero"),
+            ErrorKind::Overflow => write!(f, "integer overflow"),
+            ErrorKind::UserError(v) => match &**v {
+                ValType::Str(v) => write!(f, "{}", v.val),
+                v => write!(f, "{}", v),
+            },
+            ErrorKind::Eval { func, msg } => write!(f, "{} -- {}", func, msg),
+        }
+    }
+}
+
+thread_local! {
+    static BACKTRACE_DEPTH: Cell<usize> = const { Cell::new(16) };
+    static EVAL_DEPTH: Cell<usize> = const { Cell::new(0) };
+    static MAX_EVAL_DEPTH: Cell<usize> = const { Cell::new(DEFAULT_EVAL_DEPTH) };
+}
+
+// Nested evaluations allowed before giving up with an error, low enough
+// for the native stack of a debug build on a 2 MiB thread
+const DEFAULT_EVAL_DEPTH: usize = 1000;
+
+// Max number of frames kept in backtraces, 0 turns them off
+pub fn set_backtrace_depth(depth: usize) {
+    BACKTRACE_DEPTH.with(|v| v.set(depth));
+}
+
+// Max number of nested evaluations, tail calls don't count
+pub fn set_eval_depth(depth: usize) {
+    MAX_EVAL_DEPTH.with(|v| v.set(depth));
+}
+
+// Held while an evaluation is in progress, counts it towards the depth
+// limit until dropped
+struct Nested;
+
+impl Nested {
+    fn enter() -> Result<Nested, ASTError> {
+        let depth = EVAL_DEPTH.with(|v| v.get());
+        if depth >= MAX_EVAL_DEPTH.with(|v| v.get()) {
+            return Err(ASTError {
+                error: ErrorKind::Eval {
+                    func: "eval".to_owned(),
+                    msg: "recursion too deep".to_owned(),
+                },
+                span: None,
+                backtrace: None,
+            });
+        }
+        EVAL_DEPTH.with(|v| v.set(depth + 1));
+        Ok(Nested)
+    }
+}
+
+impl Drop for Nested {
+    fn drop(&mut self) {
+        EVAL_DEPTH.with(|v| v.set(v.get() - 1));
+    }
+}
+
+// A call an error unwound through
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub func: String,
+    // `name = value` for each param, empty for builtins
+    pub args: String,
+    // Call site
+    pub span: Option<Span>,
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.func)?;
+        if !self.args.is_empty() {
+            write!(f, " ({})", self.args)?;
+        }
+        if let Some(span) = self.span {
+            write!(f, " at {}", span)?;
+        }
+        Ok(())
+    }
+}
+
+// Innermost frame first, frames past the depth limit are only counted
+#[derive(Debug, Default, PartialEq)]
+pub struct Backtrace {
+    pub frames: Vec<Frame>,
+    pub omitted: usize,
 }
 
 #[derive(Debug)]
 pub struct ASTError {
     pub error: ErrorKind,
+    // Innermost expression that failed, if it came from the parser
+    pub span: Option<Span>,
+    // Filled in while the error unwinds through calls
+    pub backtrace: Option<Box<Backtrace>>,
+}
+
+impl ASTError {
+    // Frames are built lazily, the closure isn't called once the depth limit
+    // is reached
+    fn push_frame(mut self, frame: impl FnOnce() -> Frame) -> ASTError {
+        let depth = BACKTRACE_DEPTH.with(|v| v.get());
+        if depth == 0 {
+            return self;
+        }
+        let bt = self.backtrace.get_or_insert_with(Default::default);
+        if bt.frames.len() < depth {
+            bt.frames.push(frame());
+        } else {
+            bt.omitted += 1;
+        }
+        self
+    }
+
+    // Keeps the span the error already has, it is closer to the cause
+    fn or_span(mut self, span: Option<Span>) -> ASTError {
+        if self.span.is_none() {
+            self.span = span;
+        }
+        self
+    }
+}
+
+impl fmt::Display for ASTError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl std::error::Error for ASTError {}
+
+impl From<NumberError> for ASTError {
+    fn from(e: NumberError) -> Self {
+        ASTError {
+            error: match e {
+                NumberError::DivisionByZero => ErrorKind::DivisionByZero,
+                NumberError::Overflow => ErrorKind::Overflow,
+            },
+            span: None,
+            backtrace: None,
+        }
+    }
 }
 
 pub struct Function {
     fun: fn(Sexpr, EnvRef) -> Result<Val, ASTError>,
+    sym: &'static str,
 }
 
 impl PartialEq for Function {
@@ -31,119 +218,414 @@ impl fmt::Debug for Function {
     }
 }
 
+// Special forms get their arguments unevaluated and, instead of evaluating
+// the expression in tail position themselves, hand it back to the evaluator
+// so that tail calls don't grow the Rust stack
+pub struct Special {
+    fun: fn(Sexpr, EnvRef) -> Result<Trampoline, ASTError>,
+    sym: &'static str,
+}
+
+impl PartialEq for Special {
+    fn eq(&self, other: &Self) -> bool {
+        self.fun as usize == other.fun as usize
+    }
+}
+
+impl fmt::Debug for Special {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Special <{}>", self.fun as usize)
+    }
+}
+
+pub enum Trampoline {
+    Done(Val),
+    // Expression left to evaluate in tail position
+    Eval(Val, EnvRef),
+    // Body of the lambda `func` in its new frame, `func` and the call site
+    // are kept for backtraces
+    Call {
+        func: Val,
+        body: Val,
+        env: EnvRef,
+        span: Option<Span>,
+    },
+}
+
+impl Trampoline {
+    fn run(mut self) -> Result<Val, ASTError> {
+        // Lambda whose body is being evaluated, a tail call replaces it just
+        // like it replaces its frame
+        let mut current: Option<(Val, EnvRef, Option<Span>)> = None;
+        loop {
+            let next = match self {
+                Trampoline::Done(v) => return Ok(v),
+                Trampoline::Eval(expr, env) => match &*expr {
+                    ValType::Sexpr(v) => v.step(env),
+                    v => v.eval(env).map(Trampoline::Done),
+                },
+                Trampoline::Call {
+                    func,
+                    body,
+                    env,
+                    span,
+                } => {
+                    current = Some((func, Rc::clone(&env), span));
+                    Ok(Trampoline::Eval(body, env))
+                }
+            };
+            self = match (next, &current) {
+                (Ok(v), _) => v,
+                (Err(e), Some((func, env, span))) => {
+                    return Err(e.push_frame(|| match &**func {
+                        ValType::Function(FuncType::Lambda(l)) => l.frame(env, *span),
+                        _ => unreachable!("only lambdas are called through the trampoline"),
+                    }))
+                }
+                (Err(e), None) => return Err(e),
+            };
+        }
+    }
+}
+
 pub struct Closure {
     fun: Box<dyn Fn(Sexpr, EnvRef) -> Result<Val, ASTError>>,
     sym: String,
 }
 
 impl PartialEq for Closure {
-    fn eq(&self, other: &Self) -> bool {
+    fn eq(&self, _other: &Self) -> bool {
         true
     }
 }
 
 impl fmt::Debug for Closure {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Closure <>")
+        write!(f, "Closure <{}>", self.sym)
+    }
+}
+
+// Parameter list of a lambda:
+//   (a b &optional c (d 1) & rest &key e (f 2))
+// Defaults are evaluated at call time in the call frame, so they can refer
+// to the parameters before them. `&rest` is a synonym of `&`.
+#[derive(PartialEq, Default)]
+struct Params {
+    required: Vec<Symbol>,
+    optional: Vec<(Symbol, Option<Val>)>,
+    rest: Option<Symbol>,
+    keys: Vec<(Symbol, Option<Val>)>,
+}
+
+impl Params {
+    fn parse(spec: &Sexpr) -> Result<Params, ASTError> {
+        #[derive(PartialEq)]
+        enum Section {
+            Required,
+            Optional,
+            Rest,
+            Key,
+        }
+        let malformed = || ASTError {
+            error: ErrorKind::Eval {
+                func: "lambda".to_owned(),
+                msg: "malformed parameter list".to_owned(),
+            },
+            span: None,
+            backtrace: None,
+        };
+        let mut ret = Params::default();
+        let mut section = Section::Required;
+        for p in spec.val.iter() {
+            let (sym, default) = match &**p {
+                ValType::Symbol(s) => match (s.val.as_str(), &section) {
+                    ("&optional", Section::Required) => {
+                        section = Section::Optional;
+                        continue;
+                    }
+                    ("&" | "&rest", Section::Required | Section::Optional) => {
+                        section = Section::Rest;
+                        continue;
+                    }
+                    ("&key", Section::Rest) if ret.rest.is_none() => return Err(malformed()),
+                    ("&key", Section::Required | Section::Optional | Section::Rest) => {
+                        section = Section::Key;
+                        continue;
+                    }
+                    (v, _) if v.starts_with('&') || v.starts_with(':') => return Err(malformed()),
+                    _ => (s.clone(), None),
+                },
+                // (name default)
+                ValType::Sexpr(v) if v.val.len() == 2 => match &*v.val[0] {
+                    ValType::Symbol(s) => (s.clone(), Some(Rc::clone(&v.val[1]))),
+                    _ => return Err(malformed()),
+                },
+                _ => return Err(malformed()),
+            };
+            match section {
+                Section::Required if default.is_none() => ret.required.push(sym),
+                Section::Optional => ret.optional.push((sym, default)),
+                Section::Rest if default.is_none() && ret.rest.is_none() => ret.rest = Some(sym),
+                Section::Key => ret.keys.push((sym, default)),
+                _ => return Err(malformed()),
+            }
+        }
+        if section == Section::Rest && ret.rest.is_none() {
+            return Err(malformed());
+        }
+        Ok(ret)
+    }
+
+    // Human readable number of accepted positional args
+    fn arity(&self) -> String {
+        let (min, max) = (self.required.len(), self.required.len() + self.optional.len());
+        if self.rest.is_some() || !self.keys.is_empty() {
+            format!("at least {}", min)
+        } else if min == max {
+            format!("{}", min)
+        } else {
+            format!("{} to {}", min, max)
+        }
+    }
+}
+
+// The list as it would be written, `&rest` is printed as `&`
+impl fmt::Display for Params {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let with_default = |(s, d): &(Symbol, Option<Val>)| match d {
+            Some(d) => format!("({} {})", s, d),
+            None => s.to_string(),
+        };
+        let mut ret: Vec<String> = self.required.iter().map(|v| v.to_string()).collect();
+        if !self.optional.is_empty() {
+            ret.push("&optional".to_owned());
+            ret.extend(self.optional.iter().map(with_default));
+        }
+        if let Some(s) = &self.rest {
+            ret.push(format!("& {}", s));
+        }
+        if !self.keys.is_empty() {
+            ret.push("&key".to_owned());
+            ret.extend(self.keys.iter().map(with_default));
+        }
+        write!(f, "({})", ret.join(" "))
     }
 }
 
 #[derive(PartialEq)]
 pub struct Lambda {
-    params: Vec<Symbol>,
+    params: Params,
     body: Val,
-    env: Option<Env>,
+    // Environment the lambda was created in, call frames are its children
+    env: EnvRef,
+    // Set by setq when the lambda is first bound, used in error messages
+    name: RefCell<Option<String>>,
+    // Params and body as written in the source, if they were
+    span: Option<Span>,
 }
 
 impl Lambda {
-
-    fn new_partial(body: Val, params: Vec<Symbol>, env: Env)  -> Result<Val, ASTError> {
-        Ok(Rc::new(ValType::Function(FuncType::Lambda(Lambda {
-            params,
-            body,
-            env: Some(env),
-        }))))
+    // Parameter list as written, e.g. (x &optional (y 1))
+    pub fn signature(&self) -> String {
+        self.params.to_string()
     }
-  //  pub fn new_val(body: Val, params: Val, env: EnvRef) -> Result<Val, ASTError> {
-    pub fn new_val(body: Val, params: Val) -> Result<Val, ASTError> {
-        let body = match &*body {
-            ValType::Sexpr(_) => body,
-            _ => {
+
+    pub fn new_val(body: Val, params: Val, env: EnvRef) -> Result<Val, ASTError> {
+        let body_span = match &*body {
+            ValType::Sexpr(v) => v.span,
+            v => {
                 return Err(ASTError {
-                    error: ErrorKind::ErrorEval("setq -- expected Sexpr as a first arg"),
+                    error: ErrorKind::TypeMismatch {
+                        func: "lambda".to_owned(),
+                        expected: "list as a body",
+                        got: v.type_name(),
+                    },
+                    span: None,
+                    backtrace: None,
                 })
             }
         };
 
-        let params = match &*params {
-            ValType::Sexpr(v) => v,
-            _ => {
+        let (params, params_span) = match &*params {
+            ValType::Sexpr(v) => (Params::parse(v)?, v.span),
+            v => {
                 return Err(ASTError {
-                    error: ErrorKind::ErrorEval("setq -- expected Sexpr as a first arg"),
+                    error: ErrorKind::TypeMismatch {
+                        func: "lambda".to_owned(),
+                        expected: "list of params",
+                        got: v.type_name(),
+                    },
+                    span: None,
+                    backtrace: None,
                 })
             }
         };
-        let params = {
-            let v: Result<Vec<Symbol>, ASTError> = params
-                .val
-                .iter()
-                .map(|x| match &**x {
-                    ValType::Symbol(v) => Ok(v.clone()),
-                    _ => Err(ASTError {
-                        error: ErrorKind::ErrorEval("Lambda passed not a symbol"),
-                    }),
-                })
-                .collect();
-            v?
-        };
-
-        let env = Env::new(None);
 
         Ok(Rc::new(ValType::Function(FuncType::Lambda(Lambda {
             params,
             body,
-            env: None,
+            env,
+            name: RefCell::new(None),
+            span: match (params_span, body_span) {
+                (Some(p), Some(b)) => Some(p.to(b)),
+                (p, b) => p.or(b),
+            },
         }))))
     }
 
-    fn call(&self, val: Sexpr, parent_env: EnvRef) -> Result<Val, ASTError> {
-//        if val.val.len() > self.params.len() {
-//            return Err(ASTError {
-//                error: ErrorKind::ErrorEval("lambda eval -- too many args"),
-//            });
-//        }
-        println!("{}", "Lambda called!");
-        let mut env = match &self.env {
-            None => Env::new(None),
-            Some(e) => e.clone(),
+    // Number of required params
+    pub fn min_arity(&self) -> usize {
+        self.params.required.len()
+    }
 
-        };
-        let mut params = self.params.clone();
-        for v in val.val.into_iter().rev() {
-            let s = match params.pop() {
-                None => return Err(ASTError {
-                error: ErrorKind::ErrorEval("lambda eval -- too many args"),
-            }),
-                Some(v) => v.val,
-            };
-            
-            env.put(s, v);
-        };
+    pub fn name(&self) -> String {
+        match &*self.name.borrow() {
+            Some(v) => v.to_owned(),
+            None => "lambda".to_owned(),
+        }
+    }
 
+    // Only the first name sticks, so that (setq '(g) f) keeps reporting f
+    pub fn set_name(&self, name: &str) {
+        let mut v = self.name.borrow_mut();
+        if v.is_none() {
+            *v = Some(name.to_owned());
+        }
+    }
 
-        if params.len() > 0 {
-        let body = Val::clone(&self.body);
-            Lambda::new_partial(body, params, env)
-        } else {
-            env = env.set_parent(Some(parent_env)).unwrap();
-            self.body.eval(Rc::new(env))
+    fn arity_error(&self, got: usize) -> ASTError {
+        ASTError {
+            error: ErrorKind::Arity {
+                func: self.name(),
+                expected: self.params.arity(),
+                got,
+                defined: self.span.map(Box::new),
+            },
+            span: None,
+            backtrace: None,
         }
+    }
 
+    // The caller's environment is deliberately ignored: the body only sees
+    // its arguments and whatever was visible where the lambda was created.
+    // The body itself is left to the evaluator, it is in tail position.
+    fn call(&self, val: Sexpr) -> Result<EnvRef, ASTError> {
+        let got = val.val.len();
+        let env = Rc::new(Env::new(Some(Rc::clone(&self.env))));
+        let mut args = val.val.into_iter();
 
-        
+        // Under-application is an error rather than an implicit partial
+        // application, see the partial and curry builtins for that
+        if got < self.params.required.len() {
+            return Err(self.arity_error(got));
+        }
 
+        for (s, v) in self.params.required.iter().zip(args.by_ref()) {
+            env.put(s.val.to_owned(), v);
+        }
+        for (s, default) in self.params.optional.iter() {
+            let v = match (args.next(), default) {
+                (Some(v), _) => v,
+                (None, Some(d)) => d.eval(Rc::clone(&env))?,
+                (None, None) => Rc::new(ValType::Nil),
+            };
+            env.put(s.val.to_owned(), v);
+        }
+        let rest: Vec<Val> = args.collect();
 
+        if !self.params.keys.is_empty() {
+            self.bind_keys(&rest, &env)?;
+        } else if self.params.rest.is_none() && !rest.is_empty() {
+            return Err(self.arity_error(got));
+        }
+        if let Some(s) = &self.params.rest {
+            env.put(s.val.to_owned(), Rc::new(ValType::Sexpr(Sexpr::new(rest))));
+        }
 
+        Ok(env)
+    }
+
+    // `func` is the value wrapping self, `span` the call site
+    fn enter(&self, func: &Val, val: Sexpr, span: Option<Span>) -> Result<Trampoline, ASTError> {
+        Ok(Trampoline::Call {
+            func: Rc::clone(func),
+            body: Rc::clone(&self.body),
+            env: self.call(val)?,
+            span,
+        })
+    }
+
+    // Backtrace entry with the args as bound in the call frame `env`
+    fn frame(&self, env: &EnvRef, span: Option<Span>) -> Frame {
+        let p = &self.params;
+        let names = p
+            .required
+            .iter()
+            .chain(p.optional.iter().map(|(s, _)| s))
+            .chain(p.rest.iter())
+            .chain(p.keys.iter().map(|(s, _)| s));
+        let args: Vec<String> = names
+            .map(|s| match env.get(&s.val) {
+                Some(v) => format!("{} = {}", s.val, summary(&v)),
+                None => s.val.to_owned(),
+            })
+            .collect();
+        Frame {
+            func: self.name(),
+            args: args.join(", "),
+            span,
+        }
+    }
+
+    // Keyword args are passed as `:name value` pairs after the positional ones
+    fn bind_keys(&self, rest: &[Val], env: &EnvRef) -> Result<(), ASTError> {
+        if !rest.len().is_multiple_of(2) {
+            return Err(ASTError {
+                error: ErrorKind::Eval {
+                    func: self.name(),
+                    msg: "keyword args must come in pairs".to_owned(),
+                },
+                span: None,
+                backtrace: None,
+            });
+        }
+        let mut given: Vec<(&str, &Val)> = Vec::new();
+        for pair in rest.chunks(2) {
+            let key = match &*pair[0] {
+                ValType::Symbol(s) if s.val.starts_with(':') => &s.val[1..],
+                v => {
+                    return Err(ASTError {
+                        error: ErrorKind::TypeMismatch {
+                            func: self.name(),
+                            expected: "keyword",
+                            got: v.type_name(),
+                        },
+                        span: None,
+                        backtrace: None,
+                    })
+                }
+            };
+            if !self.params.keys.iter().any(|(s, _)| s.val == key) {
+                return Err(ASTError {
+                    error: ErrorKind::Eval {
+                        func: self.name(),
+                        msg: format!("unknown keyword :{}", key),
+                    },
+                    span: None,
+                    backtrace: None,
+                });
+            }
+            given.push((key, &pair[1]));
+        }
+        for (s, default) in self.params.keys.iter() {
+            let v = match (given.iter().find(|(k, _)| *k == s.val), default) {
+                (Some((_, v)), _) => Rc::clone(v),
+                (None, Some(d)) => d.eval(Rc::clone(env))?,
+                (None, None) => Rc::new(ValType::Nil),
+            };
+            env.put(s.val.to_owned(), v);
+        }
+        Ok(())
     }
 }
 
@@ -164,6 +646,7 @@ pub enum FuncType {
     Function(Function),
     Closure(Closure),
     Lambda(Lambda),
+    Special(Special),
 }
 
 impl FuncType {
@@ -171,8 +654,16 @@ impl FuncType {
     //        Function { fun }
     //    }
 
-    pub fn new_function(fun: fn(Sexpr, EnvRef) -> Result<Val, ASTError>) -> Val {
-        Rc::new(ValType::Function(FuncType::Function(Function { fun })))
+    // `sym` is the name the builtin is registered under, used for printing
+    pub fn new_function(fun: fn(Sexpr, EnvRef) -> Result<Val, ASTError>, sym: &'static str) -> Val {
+        Rc::new(ValType::Function(FuncType::Function(Function { fun, sym })))
+    }
+
+    pub fn new_special(
+        fun: fn(Sexpr, EnvRef) -> Result<Trampoline, ASTError>,
+        sym: &'static str,
+    ) -> Val {
+        Rc::new(ValType::Function(FuncType::Special(Special { fun, sym })))
     }
 
     pub fn new_closure(
@@ -180,11 +671,21 @@ impl FuncType {
         sym: &str,
     ) -> Val {
         Rc::new(ValType::Function(FuncType::Closure(Closure {
-            fun: fun,
+            fun,
             sym: sym.to_owned(),
         })))
     }
 
+    // Name the builtin was registered under, None for lambdas
+    pub fn builtin_name(&self) -> Option<&str> {
+        match self {
+            FuncType::Function(v) => Some(v.sym),
+            FuncType::Closure(v) => Some(&v.sym),
+            FuncType::Special(v) => Some(v.sym),
+            FuncType::Lambda(_) => None,
+        }
+    }
+
     // Uncomment to realize that Sexpr::eval is the only good place
     // to eval function (no need to clone or smth)
     //fn eval(self, val: Val, env: &Env) -> Result<Val, ASTError> {
@@ -201,69 +702,122 @@ impl FuncType {
     //}
 }
 
-#[derive(Debug, PartialEq)]
-pub struct Number {
-    pub val: i128,
-}
-
-impl Number {
-    pub fn new(val: i128) -> Number {
-        Number { val }
-    }
-
-    fn eval(&self) -> Result<Val, ASTError> {
-        Ok(Rc::new(ValType::Number(Number { val: self.val })))
+impl fmt::Display for FuncType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FuncType::Function(v) => write!(f, "<builtin {}>", v.sym),
+            FuncType::Closure(v) => write!(f, "<builtin {}>", v.sym),
+            FuncType::Special(v) => write!(f, "<builtin {}>", v.sym),
+            FuncType::Lambda(v) => write!(f, "<lambda {}>", v.params),
+        }
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug)]
 pub struct Sexpr {
     pub val: Vec<Val>,
+    // Set for lists read by the parser, None for ones built at runtime
+    pub span: Option<Span>,
+}
+
+// Spans don't take part in comparison, a list read from source equals the
+// same list built at runtime
+impl PartialEq for Sexpr {
+    fn eq(&self, other: &Self) -> bool {
+        self.val == other.val
+    }
 }
 
 impl Sexpr {
     pub fn new(val: Vec<Val>) -> Sexpr {
-        Sexpr { val }
+        Sexpr { val, span: None }
+    }
+
+    pub fn new_spanned(val: Vec<Val>, span: Span) -> Sexpr {
+        Sexpr {
+            val,
+            span: Some(span),
+        }
     }
 
     fn eval(&self, env: EnvRef) -> Result<Val, ASTError> {
-        let mut val = {
+        let _nested = Nested::enter()?;
+        self.step(env)?.run()
+    }
+
+    // Errors raised while evaluating this list point at it, unless
+    // something nested already claimed them
+    fn step(&self, env: EnvRef) -> Result<Trampoline, ASTError> {
+        self.step_unspanned(env).map_err(|e| e.or_span(self.span))
+    }
+
+    // Evaluates everything but the expression in tail position
+    fn step_unspanned(&self, env: EnvRef) -> Result<Trampoline, ASTError> {
+        if self.val.is_empty() {
+            return Ok(Trampoline::Done(Rc::new(ValType::Nil)));
+        }
+        // The head is evaluated first: special forms must see the rest of
+        // the list before anything in it is evaluated
+        let head = ValType::eval(&self.val[0], Rc::clone(&env))?;
+        if let ValType::Function(FuncType::Special(fun)) = &*head {
+            return (fun.fun)(Sexpr::new(self.val[1..].to_vec()), env);
+        }
+        let val = {
             let v: Result<Vec<Val>, ASTError> =
-                self.val.iter().map(|x| ValType::eval(&*x, Rc::clone(&env))).collect();
+                self.val[1..].iter().map(|x| ValType::eval(x, Rc::clone(&env))).collect();
             v?
         };
-        //        for v in self.val.iter_mut() {
-        //            // Memove??
-        //            *v = v.eval()?;
-        //        }
 
         match self.val.len() {
-            0 => Ok(Rc::new(ValType::Nil)),
-            1 => Ok(val.remove(0)),
-            _ => match &*val.remove(0) {
-                ValType::Function(fun) => match fun {
-                    FuncType::Function(fun) => (fun.fun)(Sexpr::new(val), env),
-                    FuncType::Closure(fun) => (fun.fun)(Sexpr::new(val), env),
-                    FuncType::Lambda(fun) => fun.call(Sexpr::new(val), env),
-                },
-                _ => Err(ASTError {
-                    error: ErrorKind::ErrorEval("Not a symbol!"),
+            1 if !matches!(&*head, ValType::Function(_)) => Ok(Trampoline::Done(head)),
+            _ => match &*head {
+                ValType::Function(_) => apply_at(&head, Sexpr::new(val), env, self.span),
+                v => Err(ASTError {
+                    error: ErrorKind::TypeMismatch {
+                        func: "eval".to_owned(),
+                        expected: "function at the head of a list",
+                        got: v.type_name(),
+                    },
+                    span: None,
+                    backtrace: None,
                 }),
             },
         }
     }
 }
 
+impl fmt::Display for Sexpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(")?;
+        for (i, x) in self.val.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}", x)?;
+        }
+        write!(f, ")")
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct Qexpr {
     val: Val,
 }
 
+impl fmt::Display for Qexpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "'{}", self.val)
+    }
+}
+
 impl Qexpr {
-    pub fn new(val: Sexpr) -> Qexpr {
-        Qexpr {
-            val: Rc::new(ValType::Sexpr(val)),
-        }
+    pub fn new(val: ValType) -> Qexpr {
+        Qexpr { val: Rc::new(val) }
+    }
+
+    // The quoted value
+    pub fn quoted(&self) -> &Val {
+        &self.val
     }
 
     fn eval(&self, _: EnvRef) -> Result<Val, ASTError> {
@@ -271,22 +825,132 @@ impl Qexpr {
     }
 }
 
+// Mutable cons cell. Proper lists are still Sexprs, pairs only show up for
+// dotted/improper lists and cells built with cons onto a non-list.
+#[derive(Debug, PartialEq)]
+pub struct Pair {
+    pub car: RefCell<Val>,
+    pub cdr: RefCell<Val>,
+}
+
+impl Pair {
+    pub fn new(car: Val, cdr: Val) -> Pair {
+        Pair {
+            car: RefCell::new(car),
+            cdr: RefCell::new(cdr),
+        }
+    }
+
+    // Builds the value of (a b ... . tail): a proper list if the tail is a
+    // list itself, a chain of pairs otherwise
+    pub fn new_list(mut init: Vec<Val>, tail: Val) -> ValType {
+        match &*tail {
+            ValType::Sexpr(v) => {
+                init.extend(v.val.iter().cloned());
+                ValType::Sexpr(Sexpr::new(init))
+            }
+            ValType::Nil => ValType::Sexpr(Sexpr::new(init)),
+            _ => {
+                let last = init.pop().expect("dotted list without car");
+                init.into_iter().rev().fold(
+                    ValType::Pair(Pair::new(last, tail)),
+                    |cdr, car| ValType::Pair(Pair::new(car, Rc::new(cdr))),
+                )
+            }
+        }
+    }
+
+    // Elements of the chain of pairs starting here, and its tail unless
+    // the chain ends in a proper list. A chain that loops back into itself
+    // (see set-cdr!) stops at the first pair it comes back to, which is
+    // returned as the tail.
+    pub fn items(&self) -> (Vec<Val>, Option<Val>) {
+        let mut seen = HashSet::new();
+        seen.insert(self as *const Pair);
+        let mut items = vec![Rc::clone(&self.car.borrow())];
+        let mut cdr = Rc::clone(&self.cdr.borrow());
+        loop {
+            let next = match &*cdr {
+                ValType::Pair(p) if !seen.insert(p as *const Pair) => return (items, Some(cdr)),
+                ValType::Pair(p) => {
+                    items.push(Rc::clone(&p.car.borrow()));
+                    Rc::clone(&p.cdr.borrow())
+                }
+                ValType::Sexpr(v) => {
+                    items.extend(v.val.iter().cloned());
+                    return (items, None);
+                }
+                ValType::Nil => return (items, None),
+                _ => return (items, Some(Rc::clone(&cdr))),
+            };
+            cdr = next;
+        }
+    }
+}
+
+impl fmt::Display for Pair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", printer::print_pair(self, Style::Write))
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
+pub struct Str {
+    pub val: String,
+}
+
+impl Str {
+    pub fn new(val: String) -> Str {
+        Str { val }
+    }
+
+    fn eval(&self) -> Result<Val, ASTError> {
+        Ok(Rc::new(ValType::Str(self.clone())))
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Symbol {
     pub val: String,
+    pub span: Option<Span>,
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        self.val == other.val
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.val)
+    }
 }
 
 impl Symbol {
     pub fn new(val: String) -> Symbol {
-        Symbol { val }
+        Symbol { val, span: None }
+    }
+
+    pub fn new_spanned(val: String, span: Span) -> Symbol {
+        Symbol {
+            val,
+            span: Some(span),
+        }
     }
 
+    // Keywords (:name) evaluate to themselves
     fn eval(&self, env: EnvRef) -> Result<Val, ASTError> {
+        if self.val.starts_with(':') {
+            return Ok(Rc::new(ValType::Symbol(self.clone())));
+        }
         match env.get(&self.val) {
             Some(v) => Ok(v),
-            None => { println!("{}", self.val); Err(ASTError {
-                error: ErrorKind::ErrorEval("Sym not found!"),
-            })},
+            None => Err(ASTError {
+                error: ErrorKind::UnboundSymbol(self.val.to_owned()),
+                span: self.span,
+                backtrace: None,
+            }),
         }
     }
 }
@@ -295,9 +959,12 @@ impl Symbol {
 #[derive(Debug, PartialEq)]
 pub enum ValType {
     Number(Number),
+    Str(Str),
+    Bool(bool),
     Sexpr(Sexpr),
     Qexpr(Qexpr),
     Symbol(Symbol),
+    Pair(Pair),
     Function(FuncType),
     Nil,
 }
@@ -305,22 +972,76 @@ pub enum ValType {
 pub type Val = Rc<ValType>;
 
 impl ValType {
-    fn eval(&self, env: EnvRef) -> Result<Val, ASTError> {
+    // Only #f and Nil are false, everything else (including 0 and the empty
+    // list) is true
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, ValType::Bool(false) | ValType::Nil)
+    }
+
+    // Used in type mismatch errors
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            ValType::Number(_) => "number",
+            ValType::Str(_) => "string",
+            ValType::Bool(_) => "bool",
+            ValType::Sexpr(v) if v.val.is_empty() => "empty list",
+            ValType::Sexpr(_) => "list",
+            ValType::Qexpr(_) => "quoted expression",
+            ValType::Symbol(_) => "symbol",
+            ValType::Pair(_) => "pair",
+            ValType::Function(_) => "function",
+            ValType::Nil => "nil",
+        }
+    }
+
+    pub(crate) fn eval(&self, env: EnvRef) -> Result<Val, ASTError> {
         match &self {
-            ValType::Number(v) => v.eval(),
+            ValType::Number(v) => Ok(Rc::new(ValType::Number(v.clone()))),
+            ValType::Str(v) => v.eval(),
+            ValType::Bool(v) => Ok(Rc::new(ValType::Bool(*v))),
             ValType::Sexpr(v) => v.eval(env),
             ValType::Qexpr(v) => v.eval(env),
             ValType::Symbol(v) => v.eval(env),
             ValType::Nil => Ok(Rc::new(ValType::Nil)),
+            ValType::Pair(_) => Err(ASTError {
+                error: ErrorKind::Eval {
+                    func: "eval".to_owned(),
+                    msg: "dotted pair can't be evaluated, quote it".to_owned(),
+                },
+                span: None,
+                backtrace: None,
+            }),
             ValType::Function(_) => Err(ASTError {
-                error: ErrorKind::ErrorEval(
-                    "Function tried to evaluate -- this should not have happened",
-                ),
+                error: ErrorKind::Eval {
+                    func: "eval".to_owned(),
+                    msg: "function tried to evaluate -- this should not have happened".to_owned(),
+                },
+                span: None,
+                backtrace: None,
             }),
         }
     }
 }
 
+// Lisp syntax, strings are printed as literals so that the output can be
+// read back
+impl fmt::Display for ValType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValType::Number(v) => write!(f, "{}", v),
+            ValType::Str(v) => write!(f, "{:?}", v.val),
+            ValType::Bool(true) => write!(f, "#t"),
+            ValType::Bool(false) => write!(f, "#f"),
+            ValType::Sexpr(v) => write!(f, "{}", v),
+            ValType::Qexpr(v) => write!(f, "{}", v),
+            ValType::Symbol(v) => write!(f, "{}", v),
+            ValType::Pair(v) => write!(f, "{}", v),
+            ValType::Function(v) => write!(f, "{}", v),
+            ValType::Nil => write!(f, "()"),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct AST {
     a_type: Val,
@@ -334,3 +1055,241 @@ impl AST {
         self.a_type.eval(env)
     }
 }
+
+// Evaluates top-level forms in order in one env, returns the value of the
+// last one (nil for an empty program)
+pub fn eval_program(program: &[AST], env: EnvRef) -> Result<Val, ASTError> {
+    let mut ret = Rc::new(ValType::Nil);
+    for form in program {
+        ret = form.eval(Rc::clone(&env))?;
+    }
+    Ok(ret)
+}
+
+// Values in backtraces are cut short
+fn summary(v: &Val) -> String {
+    let ret = v.to_string();
+    match ret.char_indices().nth(24) {
+        Some((i, _)) => format!("{}...", &ret[..i]),
+        None => ret,
+    }
+}
+
+// Applies a function to already evaluated args. Lambda bodies are left to
+// the trampoline, `span` is the call site.
+fn apply_at(fun: &Val, val: Sexpr, env: EnvRef, span: Option<Span>) -> Result<Trampoline, ASTError> {
+    let builtin = |func: &str, e: ASTError| {
+        e.push_frame(|| Frame {
+            func: func.to_owned(),
+            args: String::new(),
+            span,
+        })
+    };
+    match &**fun {
+        ValType::Function(FuncType::Function(f)) => {
+            (f.fun)(val, env).map(Trampoline::Done).map_err(|e| builtin(f.sym, e))
+        }
+        ValType::Function(FuncType::Closure(f)) => {
+            (f.fun)(val, env).map(Trampoline::Done).map_err(|e| builtin(&f.sym, e))
+        }
+        ValType::Function(FuncType::Lambda(f)) => f.enter(fun, val, span),
+        ValType::Function(FuncType::Special(_)) => Err(ASTError {
+            error: ErrorKind::Eval {
+                func: "apply".to_owned(),
+                msg: "special forms can't be applied to evaluated args".to_owned(),
+            },
+            span: None,
+            backtrace: None,
+        }),
+        v => Err(ASTError {
+            error: ErrorKind::TypeMismatch {
+                func: "apply".to_owned(),
+                expected: "function",
+                got: v.type_name(),
+            },
+            span: None,
+            backtrace: None,
+        }),
+    }
+}
+
+// Applies a function to already evaluated args
+pub fn apply(fun: &Val, val: Sexpr, env: EnvRef) -> Result<Val, ASTError> {
+    let _nested = Nested::enter()?;
+    apply_at(fun, val, env, None)?.run()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{err, eval, list, num, Session};
+
+    #[test]
+    fn eval_simple_ast_works() {
+        assert_eq!(eval("(+ 2 2)"), num(4));
+        assert_eq!(eval(":key"), Rc::new(ValType::Symbol(Symbol::new(":key".to_owned()))));
+    }
+
+    #[test]
+    fn eval_closures_works() {
+        let s = Session::default();
+        s.eval("(setq '(make-adder) (\\ '(x) '(\\ '(y) '(+ x y))))");
+        s.eval("(setq '(add2 add10) (make-adder 2) (make-adder 10))");
+        assert_eq!(s.eval("(add2 3)"), num(5));
+        assert_eq!(s.eval("(add10 3)"), num(13));
+        assert_eq!(s.eval("((make-adder 1) 1)"), num(2));
+
+        // Each counter owns its n, set! rebinds the captured one
+        s.eval("(setq '(make-counter) (\\ '() '(begin (setq '(n) 0) (\\ '() '(begin (set! '(n) (+ n 1)) n)))))");
+        s.eval("(setq '(c1 c2) (make-counter) (make-counter))");
+        assert_eq!(s.eval("(c1)"), num(1));
+        assert_eq!(s.eval("(c1)"), num(2));
+        assert_eq!(s.eval("(c2)"), num(1));
+
+        // The body sees the defining scope, not the caller's
+        s.eval("(setq '(x) 100)");
+        s.eval("(setq '(get-x) (\\ '() '(begin x)))");
+        s.eval("(setq '(shadow) (\\ '(x) '(get-x)))");
+        assert_eq!(s.eval("(shadow 1)"), num(100));
+
+        s.eval("(setq '(nest) (\\ '(a) '(\\ '(b) '(\\ '(c) '(- a b c)))))");
+        assert_eq!(s.eval("(((nest 10) 3) 2)"), num(5));
+
+        assert!(s.run("(set! '(undefined) 1)").is_err());
+    }
+
+    #[test]
+    fn eval_tail_calls_works() {
+        // Tail position in if, cond, when, begin, and, or and the lambda body.
+        // The stack is small enough that 100000 nested calls would overflow
+        // it, so this only passes if tail calls use constant stack.
+        let run = || {
+            let s = Session::default();
+            s.eval("(setq '(count) (\\ '(n acc) '(if (= n 0) acc (begin (count (- n 1) (+ acc 1))))))");
+            assert_eq!(s.eval("(count 100000 0)"), num(100000));
+            s.eval("(setq '(count-cond) (\\ '(n) '(cond ((= n 0) 0) (else (when #t (count-cond (- n 1)))))))");
+            assert_eq!(s.eval("(count-cond 100000)"), num(0));
+            s.eval("(setq '(count-or) (\\ '(n) '(or (= n 0) (and #t (count-or (- n 1))))))");
+            assert_eq!(s.eval("(count-or 100000)"), Rc::new(ValType::Bool(true)));
+        };
+        std::thread::Builder::new().stack_size(256 * 1024).spawn(run).unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn eval_depth_limit_works() {
+        // Deep non-tail recursion is an error, not a stack overflow, and
+        // leaves the session usable. Runs with the stack of a main thread.
+        let run = || {
+            let s = Session::default();
+            s.eval("(setq '(f) (\\ '(n) '(if (= n 0) 0 (+ 1 (f (- n 1))))))");
+            assert_eq!(s.err("(f 5000)").to_string(), "eval -- recursion too deep");
+            assert_eq!(s.eval("(f 100)"), num(100));
+            set_eval_depth(50);
+            assert_eq!(s.err("(f 100)").to_string(), "eval -- recursion too deep");
+            assert_eq!(s.eval("(f 10)"), num(10));
+        };
+        std::thread::Builder::new().stack_size(8 << 20).spawn(run).unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn eval_lambda_params_works() {
+        let s = Session::default();
+        s.eval("(setq '(f) (\\ '(x & rest) '(begin rest)))");
+        assert_eq!(s.eval("(f 1 2 3)"), list(vec![num(2), num(3)]));
+        assert_eq!(s.eval("(f 1)"), list(vec![]));
+
+        s.eval("(setq '(g) (\\ '(x &optional (y (* x 2)) z) '(begin (list x y z))))");
+        assert_eq!(s.eval("(g 1)"), list(vec![num(1), num(2), Rc::new(ValType::Nil)]));
+        assert_eq!(s.eval("(g 1 5 6)"), list(vec![num(1), num(5), num(6)]));
+
+        s.eval("(setq '(h) (\\ '(x &key (y 10) z) '(begin (list x y z))))");
+        assert_eq!(s.eval("(h 1 :z 3)"), list(vec![num(1), num(10), num(3)]));
+        assert_eq!(s.eval("(h 1 :z 3 :y 2)"), list(vec![num(1), num(2), num(3)]));
+        assert!(s.run("(h 1 :w 3)").is_err());
+        assert!(s.run("(h 1 :z)").is_err());
+
+        match s.err("(g 1 2 3 4)").error {
+            ErrorKind::Arity { func, expected, got, .. } => {
+                assert_eq!((func.as_str(), expected.as_str(), got), ("g", "1 to 3", 4));
+            }
+            e => panic!("unexpected error {:?}", e),
+        }
+        s.eval("(setq '(two) (\\ '(a b) '(+ a b)))");
+        match s.err("(two 1 2 3)").error {
+            ErrorKind::Arity { func, expected, got, .. } => {
+                assert_eq!((func.as_str(), expected.as_str(), got), ("two", "2", 3));
+            }
+            e => panic!("unexpected error {:?}", e),
+        }
+
+        assert!(s.run("(\\ '(a & b c) '(+ 1))").is_err());
+        assert!(s.run("(\\ '(a &) '(+ 1))").is_err());
+        assert!(s.run("(\\ '(a (b 1)) '(+ 1))").is_err());
+    }
+
+    #[test]
+    fn eval_structured_errors_works() {
+        assert!(matches!(err("(+ 1 nope)").error, ErrorKind::UnboundSymbol(v) if v == "nope"));
+        assert!(matches!(err("(set! '(nope) 1)").error, ErrorKind::UnboundSymbol(v) if v == "nope"));
+        match err("(+ 1 \"a\")").error {
+            ErrorKind::TypeMismatch { func, expected, got } => {
+                assert_eq!((func.as_str(), expected, got), ("+", "numbers", "string"))
+            }
+            e => panic!("unexpected error {:?}", e),
+        }
+        match err("(car 1 2)").error {
+            ErrorKind::Arity { func, expected, got, defined } => {
+                assert_eq!((func.as_str(), expected.as_str(), got, defined), ("car", "1", 2, None))
+            }
+            e => panic!("unexpected error {:?}", e),
+        }
+        match err("(error '(bad 1))").error {
+            ErrorKind::UserError(v) => assert_eq!(v.to_string(), "(bad 1)"),
+            e => panic!("unexpected error {:?}", e),
+        }
+        assert!(matches!(err("(nth {1} 5)").error, ErrorKind::Eval { .. }));
+
+        assert_eq!(err("(error \"boom\")").to_string(), "boom");
+        assert_eq!(err("(< 1 \"a\")").to_string(), "< -- expected number, got string");
+        assert_eq!(err("(1 2)").to_string(), "eval -- expected function at the head of a list, got number");
+        assert_eq!(err("(substring \"abc\" 2 1)").to_string(), "substring -- index out of range");
+        let e: Box<dyn std::error::Error> = Box::new(err("(/ 1 0)"));
+        assert_eq!(e.to_string(), "division by zero");
+    }
+
+    #[test]
+    fn eval_backtrace_works() {
+        let frames = |input| {
+            let bt = err(input).backtrace.unwrap_or_default();
+            let mut ret: Vec<String> = bt.frames.iter().map(|v| v.to_string()).collect();
+            if bt.omitted > 0 {
+                ret.push(format!("+{}", bt.omitted));
+            }
+            ret
+        };
+        assert_eq!(
+            frames("(setq '(f) (\\ '(x) '(car x)))\n(setq '(g) (\\ '(y) '(+ 1 (f y))))\n(g {})"),
+            vec!["car at 1:21", "f (x = ()) at 2:26", "g (y = ()) at 3:1"]
+        );
+        // tail calls replace their caller's frame
+        assert_eq!(
+            frames("(setq '(f) (\\ '(x) '(car x)))\n(setq '(g) (\\ '(y) '(f y)))\n(g {})"),
+            vec!["car at 1:21", "f (x = ()) at 2:21"]
+        );
+        // long args are cut short
+        assert_eq!(
+            frames("(setq '(f) (\\ '(x) '(/ 1 0)))\n(f \"abcdefghijklmnopqrstuvwxyz\")"),
+            vec!["/ at 1:21", "f (x = \"abcdefghijklmnopqrstuvw...) at 2:1"]
+        );
+        // builtins get a frame without args, named by their symbol, also
+        // when applied by another builtin
+        assert_eq!(frames("(/ 1 0)"), vec!["/ at 1:1"]);
+        assert_eq!(frames("((curry / 2) 1 0)"), vec!["/", "curry at 1:1"]);
+        set_backtrace_depth(2);
+        assert_eq!(
+            frames("(setq '(f) (\\ '(n) '(if (= n 0) (/ 1 0) (+ 1 (f (- n 1))))))\n(f 4)"),
+            vec!["/ at 1:33", "f (n = 0) at 1:46", "+4"]
+        );
+        set_backtrace_depth(16);
+    }
+}
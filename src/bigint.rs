@@ -0,0 +1,453 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+// Sign-magnitude arbitrary precision integer. The magnitude is stored as
+// little-endian base 2^32 limbs without trailing zero limbs, so zero is an
+// empty vector (and is never negative). This keeps the derived PartialEq
+// structural equality identical to numeric equality.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BigInt {
+    neg: bool,
+    mag: Vec<u32>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct BigIntParseError;
+
+const BASE: u64 = 1 << 32;
+// Largest power of ten fitting into a limb, used for printing and parsing
+const DEC_BASE: u32 = 1_000_000_000;
+const DEC_DIGITS: usize = 9;
+
+fn trim(mut mag: Vec<u32>) -> Vec<u32> {
+    while mag.last() == Some(&0) {
+        mag.pop();
+    }
+    mag
+}
+
+fn cmp_mag(a: &[u32], b: &[u32]) -> Ordering {
+    if a.len() != b.len() {
+        return a.len().cmp(&b.len());
+    }
+    for (x, y) in a.iter().rev().zip(b.iter().rev()) {
+        if x != y {
+            return x.cmp(y);
+        }
+    }
+    Ordering::Equal
+}
+
+fn add_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (a, b) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut ret = Vec::with_capacity(a.len() + 1);
+    let mut carry = 0u64;
+    for (i, x) in a.iter().enumerate() {
+        let s = *x as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        ret.push(s as u32);
+        carry = s >> 32;
+    }
+    if carry > 0 {
+        ret.push(carry as u32);
+    }
+    ret
+}
+
+// Requires |a| >= |b|
+fn sub_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut ret = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, x) in a.iter().enumerate() {
+        let d = *x as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        ret.push(d as u32);
+        borrow = (d < 0) as i64;
+    }
+    trim(ret)
+}
+
+fn mul_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    if a.is_empty() || b.is_empty() {
+        return Vec::new();
+    }
+    let mut ret = vec![0u32; a.len() + b.len()];
+    for (i, x) in a.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, y) in b.iter().enumerate() {
+            let p = *x as u64 * *y as u64 + ret[i + j] as u64 + carry;
+            ret[i + j] = p as u32;
+            carry = p >> 32;
+        }
+        ret[i + b.len()] = carry as u32;
+    }
+    trim(ret)
+}
+
+fn divrem_small(a: &[u32], b: u32) -> (Vec<u32>, u32) {
+    let mut q = vec![0u32; a.len()];
+    let mut rem = 0u64;
+    for i in (0..a.len()).rev() {
+        let cur = (rem << 32) | a[i] as u64;
+        q[i] = (cur / b as u64) as u32;
+        rem = cur % b as u64;
+    }
+    (trim(q), rem as u32)
+}
+
+fn shl_bits(a: &[u32], s: u32) -> Vec<u32> {
+    let mut ret = Vec::with_capacity(a.len() + 1);
+    let mut carry = 0u32;
+    for x in a {
+        ret.push((x << s) | carry);
+        carry = if s == 0 { 0 } else { x >> (32 - s) };
+    }
+    ret.push(carry);
+    ret
+}
+
+fn shr_bits(a: &[u32], s: u32) -> Vec<u32> {
+    let mut ret = vec![0u32; a.len()];
+    for i in 0..a.len() {
+        let hi = if s == 0 { 0 } else { a.get(i + 1).map_or(0, |x| x << (32 - s)) };
+        ret[i] = (a[i] >> s) | hi;
+    }
+    trim(ret)
+}
+
+// Knuth, TAOCP vol. 2, 4.3.1, Algorithm D. Requires b to be non-empty.
+fn divrem_mag(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if cmp_mag(a, b) == Ordering::Less {
+        return (Vec::new(), a.to_vec());
+    }
+    if b.len() == 1 {
+        let (q, r) = divrem_small(a, b[0]);
+        return (q, trim(vec![r]));
+    }
+    let s = b[b.len() - 1].leading_zeros();
+    let b = trim(shl_bits(b, s));
+    let mut u = shl_bits(a, s);
+    let n = b.len();
+    let m = a.len() - n;
+    let (btop, bsec) = (b[n - 1] as u64, b[n - 2] as u64);
+    let mut q = vec![0u32; m + 1];
+    for j in (0..=m).rev() {
+        let num = ((u[j + n] as u64) << 32) | u[j + n - 1] as u64;
+        let mut qhat = num / btop;
+        let mut rhat = num % btop;
+        while qhat >= BASE || qhat * bsec > ((rhat << 32) | u[j + n - 2] as u64) {
+            qhat -= 1;
+            rhat += btop;
+            if rhat >= BASE {
+                break;
+            }
+        }
+        let mut borrow = 0i64;
+        let mut carry = 0u64;
+        for i in 0..n {
+            let p = qhat * b[i] as u64 + carry;
+            carry = p >> 32;
+            let t = u[i + j] as i64 - borrow - (p & 0xffff_ffff) as i64;
+            u[i + j] = t as u32;
+            borrow = (t < 0) as i64;
+        }
+        let t = u[j + n] as i64 - borrow - carry as i64;
+        u[j + n] = t as u32;
+        if t < 0 {
+            // qhat was one too large, add the divisor back
+            qhat -= 1;
+            let mut carry = 0u64;
+            for i in 0..n {
+                let s = u[i + j] as u64 + b[i] as u64 + carry;
+                u[i + j] = s as u32;
+                carry = s >> 32;
+            }
+            u[j + n] = u[j + n].wrapping_add(carry as u32);
+        }
+        q[j] = qhat as u32;
+    }
+    (trim(q), shr_bits(&u[..n], s))
+}
+
+impl BigInt {
+    fn new(neg: bool, mag: Vec<u32>) -> BigInt {
+        let mag = trim(mag);
+        BigInt {
+            neg: neg && !mag.is_empty(),
+            mag,
+        }
+    }
+
+    pub fn zero() -> BigInt {
+        BigInt::new(false, Vec::new())
+    }
+
+    pub fn from_i128(v: i128) -> BigInt {
+        let u = v.unsigned_abs();
+        BigInt::new(
+            v < 0,
+            vec![u as u32, (u >> 32) as u32, (u >> 64) as u32, (u >> 96) as u32],
+        )
+    }
+
+    pub fn to_i128(&self) -> Option<i128> {
+        if self.mag.len() > 4 {
+            return None;
+        }
+        let u = self
+            .mag
+            .iter()
+            .rev()
+            .fold(0u128, |acc, x| (acc << 32) | *x as u128);
+        match self.neg {
+            false if u <= i128::MAX as u128 => Some(u as i128),
+            // 2^127 wraps to i128::MIN, which is exactly -2^127
+            true if u <= i128::MIN.unsigned_abs() => Some((u as i128).wrapping_neg()),
+            _ => None,
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        let v = self
+            .mag
+            .iter()
+            .rev()
+            .fold(0f64, |acc, x| acc * BASE as f64 + *x as f64);
+        if self.neg {
+            -v
+        } else {
+            v
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mag.is_empty()
+    }
+
+    // Number of bits in the magnitude, 0 for zero
+    pub fn bits(&self) -> u64 {
+        match self.mag.last() {
+            Some(v) => self.mag.len() as u64 * 32 - v.leading_zeros() as u64,
+            None => 0,
+        }
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.neg
+    }
+
+    pub fn neg(&self) -> BigInt {
+        BigInt::new(!self.neg, self.mag.clone())
+    }
+
+    pub fn abs(&self) -> BigInt {
+        BigInt::new(false, self.mag.clone())
+    }
+
+    pub fn add(&self, other: &BigInt) -> BigInt {
+        if self.neg == other.neg {
+            return BigInt::new(self.neg, add_mag(&self.mag, &other.mag));
+        }
+        match cmp_mag(&self.mag, &other.mag) {
+            Ordering::Less => BigInt::new(other.neg, sub_mag(&other.mag, &self.mag)),
+            _ => BigInt::new(self.neg, sub_mag(&self.mag, &other.mag)),
+        }
+    }
+
+    pub fn sub(&self, other: &BigInt) -> BigInt {
+        self.add(&other.neg())
+    }
+
+    pub fn mul(&self, other: &BigInt) -> BigInt {
+        BigInt::new(self.neg != other.neg, mul_mag(&self.mag, &other.mag))
+    }
+
+    // Truncating division: the quotient is rounded towards zero and the
+    // remainder has the sign of the dividend. Returns None on division by zero.
+    pub fn divrem(&self, other: &BigInt) -> Option<(BigInt, BigInt)> {
+        if other.is_zero() {
+            return None;
+        }
+        let (q, r) = divrem_mag(&self.mag, &other.mag);
+        Some((
+            BigInt::new(self.neg != other.neg, q),
+            BigInt::new(self.neg, r),
+        ))
+    }
+
+    pub fn pow(&self, mut exp: u32) -> BigInt {
+        let mut base = self.clone();
+        let mut ret = BigInt::from_i128(1);
+        while exp > 0 {
+            if exp & 1 == 1 {
+                ret = ret.mul(&base);
+            }
+            exp >>= 1;
+            if exp > 0 {
+                base = base.mul(&base);
+            }
+        }
+        ret
+    }
+
+    pub fn gcd(&self, other: &BigInt) -> BigInt {
+        let (mut a, mut b) = (self.abs(), other.abs());
+        while !b.is_zero() {
+            let (_, r) = a.divrem(&b).unwrap();
+            a = b;
+            b = r;
+        }
+        a
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match (self.neg, other.neg) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_mag(&self.mag, &other.mag),
+            (true, true) => cmp_mag(&other.mag, &self.mag),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl FromStr for BigInt {
+    type Err = BigIntParseError;
+
+    fn from_str(s: &str) -> Result<BigInt, BigIntParseError> {
+        let (neg, digits) = match s.as_bytes().first() {
+            Some(b'-') => (true, &s[1..]),
+            Some(b'+') => (false, &s[1..]),
+            _ => (false, s),
+        };
+        if digits.is_empty() || !digits.bytes().all(|c| c.is_ascii_digit()) {
+            return Err(BigIntParseError);
+        }
+        let mut mag: Vec<u32> = Vec::new();
+        let head = digits.len() % DEC_DIGITS;
+        let chunks = std::iter::once(&digits[..head])
+            .filter(|c| !c.is_empty())
+            .chain(digits.as_bytes()[head..].chunks(DEC_DIGITS).map(|c| {
+                // Only ASCII digits here, so any byte boundary is a char boundary
+                std::str::from_utf8(c).unwrap()
+            }));
+        for chunk in chunks {
+            let scale = 10u64.pow(chunk.len() as u32);
+            let mut carry: u64 = chunk.parse().unwrap();
+            for limb in mag.iter_mut() {
+                let v = *limb as u64 * scale + carry;
+                *limb = v as u32;
+                carry = v >> 32;
+            }
+            if carry > 0 {
+                mag.push(carry as u32);
+            }
+        }
+        Ok(BigInt::new(neg, mag))
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+        let mut parts = Vec::new();
+        let mut mag = self.mag.clone();
+        while !mag.is_empty() {
+            let (q, r) = divrem_small(&mag, DEC_BASE);
+            parts.push(r);
+            mag = q;
+        }
+        if self.neg {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", parts.pop().unwrap())?;
+        for p in parts.iter().rev() {
+            write!(f, "{:09}", p)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(s: &str) -> BigInt {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn bigint_i128_roundtrip_works() {
+        for v in [0, 1, -1, u32::MAX as i128 + 1, i128::MAX, i128::MIN, i128::MIN + 1] {
+            assert_eq!(BigInt::from_i128(v).to_i128(), Some(v));
+            assert_eq!(BigInt::from_i128(v).to_string(), v.to_string());
+            assert_eq!(big(&v.to_string()), BigInt::from_i128(v));
+        }
+        assert_eq!(BigInt::from_i128(i128::MAX).add(&BigInt::from_i128(1)).to_i128(), None);
+        assert_eq!(big("-0"), BigInt::zero());
+        assert_eq!("1x".parse::<BigInt>(), Err(BigIntParseError));
+        assert_eq!("-".parse::<BigInt>(), Err(BigIntParseError));
+    }
+
+    #[test]
+    fn bigint_arithmetic_works() {
+        let a = big("123456789012345678901234567890123456789012345678901234567890");
+        let b = big("-987654321098765432109876543210");
+        assert_eq!(
+            a.mul(&b).to_string(),
+            "-121932631137021795226185032733744855963374485596337448559633\
+             622923332237463801111263526900"
+        );
+        assert_eq!(
+            a.add(&b).to_string(),
+            "123456789012345678901234567889135802467913580246791358024680"
+        );
+        assert_eq!(
+            b.sub(&a).to_string(),
+            "-123456789012345678901234567891111111110111111111011111111100"
+        );
+        let (q, r) = a.divrem(&b).unwrap();
+        assert_eq!(q.to_string(), "-124999998860937500014238281249");
+        assert_eq!(q.mul(&b).add(&r), a);
+        assert!(r.abs() < b.abs() && !r.is_negative());
+        assert_eq!(big("2").pow(100).to_string(), "1267650600228229401496703205376");
+        assert_eq!(big("-3").pow(3), big("-27"));
+        assert_eq!((BigInt::zero().bits(), big("-3").bits(), big("2").pow(100).bits()), (0, 2, 101));
+        assert_eq!(a.mul(&big("6")).gcd(&b.mul(&big("4"))), a.gcd(&b).mul(&big("2")));
+        assert!(a.divrem(&BigInt::zero()).is_none());
+    }
+
+    #[test]
+    fn bigint_divrem_matches_i128() {
+        // Exercise the normalisation and add-back paths of algorithm D with
+        // multi-limb operands small enough to check against i128
+        let vals = [
+            1i128,
+            7,
+            u32::MAX as i128,
+            1 << 32,
+            (1 << 64) - 1,
+            (1 << 63) + 12345,
+            0x7fff_ffff_0000_0001_ffff_ffff,
+            i128::MAX,
+            i128::MIN + 1,
+        ];
+        for a in vals.iter() {
+            for b in vals.iter() {
+                for (a, b) in [(*a, *b), (-*a, *b), (*a, -*b)] {
+                    let (q, r) = BigInt::from_i128(a).divrem(&BigInt::from_i128(b)).unwrap();
+                    assert_eq!(q.to_i128(), Some(a / b), "{} / {}", a, b);
+                    assert_eq!(r.to_i128(), Some(a % b), "{} % {}", a, b);
+                }
+            }
+        }
+    }
+}
@@ -8,7 +8,3 @@ fn main() {
     println!("{:?}", out);
     repl::repl("λ > ");
 }
-
-fn test(v: u8) {
-    ()
-}
@@ -1,10 +1,77 @@
+use lis2::ast::{self, eval_program, ValType};
+use lis2::diagnostics::{self, Diagnostic};
+use lis2::env::Env;
+use lis2::parser::Parser;
 use lis2::repl;
+use std::env;
+use std::fs;
+use std::io;
+use std::process;
+use std::rc::Rc;
 
-fn main() {
-    println!("lis2, v0.1.0", );
-    repl::repl("λ > ");
+fn usage() -> ! {
+    eprintln!("usage: lis2 [--backtrace N] [file.lisp | -e 'expr']...");
+    process::exit(2);
+}
+
+// Parses and evaluates `input` in `env`, exits with a non-zero status on
+// any error. `name` is used to prefix error messages.
+fn run(name: &str, input: &str, env: &Rc<Env>, print: bool) {
+    let program = match Parser::new(input).parse_program() {
+        Ok(v) => v,
+        Err(e) => {
+            let color = diagnostics::use_color(&io::stderr());
+            eprintln!("{}", Diagnostic::from(&e).render(name, input, color));
+            process::exit(1);
+        }
+    };
+    match eval_program(&program, Rc::clone(env)) {
+        Ok(v) => {
+            if print && *v != ValType::Nil {
+                println!("{}", v);
+            }
+        }
+        Err(e) => {
+            let color = diagnostics::use_color(&io::stderr());
+            eprintln!("{}", Diagnostic::from(&e).render(name, input, color));
+            process::exit(1);
+        }
+    }
 }
 
-fn test(v: u8) {
-    ()
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    // Only error backtraces depend on it, so it has to come first
+    if args.first().map(String::as_str) == Some("--backtrace") {
+        match args.get(1).and_then(|v| v.parse().ok()) {
+            Some(depth) => ast::set_backtrace_depth(depth),
+            None => usage(),
+        }
+        args.drain(..2);
+    }
+    if args.is_empty() {
+        println!("lis2, v0.1.0", );
+        repl::repl("λ > ");
+        return;
+    }
+
+    // All files and expressions share one env, in command line order
+    let env = Rc::new(Env::new(None));
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-e" => match args.next() {
+                Some(expr) => run("-e", &expr, &env, true),
+                None => usage(),
+            },
+            "-h" | "--help" => usage(),
+            path => match fs::read_to_string(path) {
+                Ok(input) => run(path, &input, &env, false),
+                Err(e) => {
+                    eprintln!("{}: {}", path, e);
+                    process::exit(1);
+                }
+            },
+        }
+    }
 }
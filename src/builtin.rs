@@ -1,5 +1,11 @@
-use crate::ast::{ASTError, ErrorKind, Number, Sexpr, Val, ValType, Lambda};
-use crate::env::{Env, EnvRef};
+use crate::ast::{self, ASTError, ErrorKind, FuncType, Number, Pair, Sexpr, Str, Symbol, Trampoline, Val, ValType, Lambda};
+use crate::env::EnvRef;
+use crate::number::NumberError;
+use crate::printer::{self, Style};
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::convert::TryFrom;
 use std::rc::Rc;
 //    fn eval_sym(val: ValType) -> Result<ValType, ASTError> {
 //        match sym.as_ref() {
@@ -17,57 +23,86 @@ use std::rc::Rc;
 //     ($val:ident, $func:ident)
 // }
 
+// A single argument is combined with `empty`, so (- 5) is -5 and (/ 2) is 1/2,
+// otherwise the first argument is the initial accumulator.
 pub fn op(
-    empty: i128,
-    _op: fn(i128, i128) -> i128,
+    name: &'static str,
+    empty: Number,
+    _op: fn(Number, Number) -> Result<Number, NumberError>,
 ) -> Box<dyn Fn(Sexpr, EnvRef) -> Result<Val, ASTError>> {
     Box::new(move |val, _| {
-        let mut empty = empty;
+        let mut nums = Vec::with_capacity(val.val.len());
         for i in val.val {
             match &*i {
-                ValType::Number(v) => empty = _op(empty, v.val),
-                _ => {
-                    return Err(ASTError {
-                        error: ErrorKind::ErrorEval("NaN"),
-                    })
-                }
+                ValType::Number(v) => nums.push(v.clone()),
+                _ => return Err(type_error(name, "numbers", Some(&i))),
             }
         }
-        Ok(Rc::new(ValType::Number(Number { val:  empty })))
+        let mut nums = nums.into_iter();
+        let mut acc = match nums.len() {
+            1 => empty.clone(),
+            _ => nums.next().unwrap_or_else(|| empty.clone()),
+        };
+        for v in nums {
+            acc = _op(acc, v)?;
+        }
+        Ok(Rc::new(ValType::Number(acc)))
     })
 }
 
 
+pub fn quotient(val: Sexpr, _: EnvRef) -> Result<Val, ASTError> {
+    check_len(&val, 2, "quotient")?;
+    let a = get_integer(&val, 0, "quotient", "integers")?;
+    let b = get_integer(&val, 1, "quotient", "integers")?;
+    Ok(Rc::new(ValType::Number(a.checked_quotient(b)?)))
+}
+
+pub fn modulo(val: Sexpr, _: EnvRef) -> Result<Val, ASTError> {
+    check_len(&val, 2, "mod")?;
+    let a = get_integer(&val, 0, "mod", "integers")?;
+    let b = get_integer(&val, 1, "mod", "integers")?;
+    Ok(Rc::new(ValType::Number(a.checked_modulo(b)?)))
+}
+
+pub fn expt(val: Sexpr, _: EnvRef) -> Result<Val, ASTError> {
+    check_len(&val, 2, "expt")?;
+    let base = get_num(&val, 0, "expt", "number as a base")?;
+    let exp = get_num(&val, 1, "expt", "number as an exponent")?;
+    Ok(Rc::new(ValType::Number(base.checked_pow(exp)?)))
+}
+
+pub fn gcd(val: Sexpr, _: EnvRef) -> Result<Val, ASTError> {
+    let mut acc = Number::new(0);
+    for i in 0..val.val.len() {
+        acc = acc.checked_gcd(get_integer(&val, i, "gcd", "integers")?)?;
+    }
+    Ok(Rc::new(ValType::Number(acc)))
+}
+
 pub fn setq(val: Sexpr, env: EnvRef) -> Result<Val, ASTError> {
     if val.val.len() < 2 {
-        return Err(ASTError {
-            error: ErrorKind::ErrorEval("setq -- number of args doesn't match"),
-        });
+        return Err(arity_error("setq", "at least 2", val.val.len()));
     };
 
     let vars = match &*val.val[0] {
         ValType::Sexpr(v) => v,
-        _ => {
-            return Err(ASTError {
-                error: ErrorKind::ErrorEval("setq -- expected Sexpr as a first arg"),
-            })
-        }
+        _ => return Err(type_error("setq", "list of symbols as a first arg", Some(&val.val[0]))),
     };
 
     if vars.val.len() != val.val.len() - 1 {
-        return Err(ASTError {
-            error: ErrorKind::ErrorEval("setq -- number of vars doesn't match"),
-        });
+        return Err(eval_error("setq", "number of vars doesn't match number of values"));
     };
 
     for (i, v) in vars.val.iter().enumerate() {
         match &**v {
-            ValType::Symbol(s) => env.put(s.val.to_owned(), Rc::clone(&val.val[i + 1])),
-            _ => {
-                return Err(ASTError {
-                    error: ErrorKind::ErrorEval("setq -- number of vars doesn't match"),
-                })
+            ValType::Symbol(s) => {
+                if let ValType::Function(FuncType::Lambda(l)) = &*val.val[i + 1] {
+                    l.set_name(&s.val);
+                }
+                env.put(s.val.to_owned(), Rc::clone(&val.val[i + 1]))
             }
+            _ => return Err(type_error("setq", "symbols as vars", Some(v))),
         };
     }
 
@@ -76,17 +111,951 @@ pub fn setq(val: Sexpr, env: EnvRef) -> Result<Val, ASTError> {
     // Checks ^^
 }
 
+// Same calling convention as setq, but rebinds the nearest existing binding
+// instead of creating a new one in the current environment
+pub fn set(val: Sexpr, env: EnvRef) -> Result<Val, ASTError> {
+    if val.val.len() < 2 {
+        return Err(arity_error("set!", "at least 2", val.val.len()));
+    };
+
+    let vars = match &*val.val[0] {
+        ValType::Sexpr(v) => v,
+        _ => return Err(type_error("set!", "list of symbols as a first arg", Some(&val.val[0]))),
+    };
+
+    if vars.val.len() != val.val.len() - 1 {
+        return Err(eval_error("set!", "number of vars doesn't match number of values"));
+    };
+
+    for (i, v) in vars.val.iter().enumerate() {
+        match &**v {
+            ValType::Symbol(s) => {
+                if env.set(&s.val, Rc::clone(&val.val[i + 1])).is_err() {
+                    return Err(ASTError {
+                        error: ErrorKind::UnboundSymbol(s.val.to_owned()),
+                        span: s.span,
+                        backtrace: None,
+                    });
+                }
+            }
+            _ => return Err(type_error("set!", "symbols as vars", Some(v))),
+        };
+    }
+
+    Ok(Rc::new(ValType::Nil))
+}
+
 pub fn lambda (mut val: Sexpr, env: EnvRef) -> Result<Val, ASTError> {
     if val.val.len() < 2 {
-        return Err(ASTError {
-            error: ErrorKind::ErrorEval("lambda -- number of args doesn't match"),
-        });
+        return Err(arity_error("lambda", "2", val.val.len()));
     };
 
     let body = val.val.pop().unwrap();
     let params = val.val.pop().unwrap();
-    Lambda::new_val(body, params)
+    Lambda::new_val(body, params, env)
+
+
+}
+
+// `got` is None when the arg is missing altogether
+fn type_error(func: &str, expected: &'static str, got: Option<&Val>) -> ASTError {
+    ASTError {
+        error: ErrorKind::TypeMismatch {
+            func: func.to_owned(),
+            expected,
+            got: got.map_or("nothing", |v| v.type_name()),
+        },
+        span: None,
+        backtrace: None,
+    }
+}
+
+// Like type_error, but a number that isn't an integer is reported by its
+// kind, "expected integers, got number" would contradict itself
+fn integer_error(func: &str, expected: &'static str, got: Option<&Val>) -> ASTError {
+    match got.map(|v| &**v) {
+        Some(ValType::Number(v)) => ASTError {
+            error: ErrorKind::TypeMismatch {
+                func: func.to_owned(),
+                expected,
+                got: v.type_name(),
+            },
+            span: None,
+            backtrace: None,
+        },
+        _ => type_error(func, expected, got),
+    }
+}
+
+fn arity_error(func: &str, expected: &str, got: usize) -> ASTError {
+    ASTError {
+        error: ErrorKind::Arity {
+            func: func.to_owned(),
+            expected: expected.to_owned(),
+            got,
+            defined: None,
+        },
+        span: None,
+        backtrace: None,
+    }
+}
+
+fn eval_error(func: &str, msg: &str) -> ASTError {
+    ASTError {
+        error: ErrorKind::Eval {
+            func: func.to_owned(),
+            msg: msg.to_owned(),
+        },
+        span: None,
+        backtrace: None,
+    }
+}
+
+fn get_str<'a>(val: &'a Sexpr, i: usize, func: &str, expected: &'static str) -> Result<&'a str, ASTError> {
+    match val.val.get(i).map(|x| &**x) {
+        Some(ValType::Str(v)) => Ok(&v.val),
+        _ => Err(type_error(func, expected, val.val.get(i))),
+    }
+}
+
+fn get_num(val: &Sexpr, i: usize, func: &str, expected: &'static str) -> Result<Number, ASTError> {
+    match val.val.get(i).map(|x| &**x) {
+        Some(ValType::Number(v)) => Ok(v.clone()),
+        _ => Err(type_error(func, expected, val.val.get(i))),
+    }
+}
+
+fn get_integer(val: &Sexpr, i: usize, func: &str, expected: &'static str) -> Result<Number, ASTError> {
+    match val.val.get(i).map(|x| &**x) {
+        Some(ValType::Number(v)) if v.is_integer() => Ok(v.clone()),
+        _ => Err(integer_error(func, expected, val.val.get(i))),
+    }
+}
+
+fn get_int(val: &Sexpr, i: usize, func: &str, expected: &'static str) -> Result<i128, ASTError> {
+    match val.val.get(i).map(|x| &**x) {
+        Some(ValType::Number(Number::Int(v))) => Ok(*v),
+        // a bignum is past any index or arity, saturate so the caller's
+        // range checks report it
+        Some(ValType::Number(Number::Big(v))) => Ok(if v.is_negative() { i128::MIN } else { i128::MAX }),
+        _ => Err(integer_error(func, expected, val.val.get(i))),
+    }
+}
+
+fn check_len(val: &Sexpr, len: usize, func: &str) -> Result<(), ASTError> {
+    if val.val.len() != len {
+        return Err(arity_error(func, &len.to_string(), val.val.len()));
+    }
+    Ok(())
+}
+
+fn new_str(val: String) -> Result<Val, ASTError> {
+    Ok(Rc::new(ValType::Str(Str::new(val))))
+}
+
+fn new_int(val: usize) -> Result<Val, ASTError> {
+    Ok(Rc::new(ValType::Number(Number::new(val as i128))))
+}
+
+pub fn concat(val: Sexpr, _: EnvRef) -> Result<Val, ASTError> {
+    let mut ret = String::new();
+    for i in 0..val.val.len() {
+        ret.push_str(get_str(&val, i, "concat", "strings")?);
+    }
+    new_str(ret)
+}
+
+pub fn string_length(val: Sexpr, _: EnvRef) -> Result<Val, ASTError> {
+    check_len(&val, 1, "string-length")?;
+    new_int(get_str(&val, 0, "string-length", "string")?.chars().count())
+}
+
+// Indices are counted in chars, not bytes, so that (substring s 0 1) never
+// cuts a multibyte character in half.
+pub fn substring(val: Sexpr, _: EnvRef) -> Result<Val, ASTError> {
+    if val.val.len() != 2 && val.val.len() != 3 {
+        return Err(arity_error("substring", "2 to 3", val.val.len()));
+    }
+    let s = get_str(&val, 0, "substring", "string as a first arg")?;
+    let len = s.chars().count() as i128;
+    let start = get_int(&val, 1, "substring", "integer as a start index")?;
+    let end = match val.val.len() {
+        3 => get_int(&val, 2, "substring", "integer as an end index")?,
+        _ => len,
+    };
+    if start < 0 || start > end || end > len {
+        return Err(eval_error("substring", "index out of range"));
+    }
+    new_str(
+        s.chars()
+            .skip(start as usize)
+            .take((end - start) as usize)
+            .collect(),
+    )
+}
+
+pub fn index_of(val: Sexpr, _: EnvRef) -> Result<Val, ASTError> {
+    check_len(&val, 2, "index-of")?;
+    let s = get_str(&val, 0, "index-of", "string as a first arg")?;
+    let pat = get_str(&val, 1, "index-of", "string as a second arg")?;
+    match s.find(pat) {
+        Some(i) => new_int(s[..i].chars().count()),
+        None => Ok(Rc::new(ValType::Number(Number::new(-1)))),
+    }
+}
+
+pub fn split(val: Sexpr, _: EnvRef) -> Result<Val, ASTError> {
+    check_len(&val, 2, "split")?;
+    let s = get_str(&val, 0, "split", "string as a first arg")?;
+    let sep = get_str(&val, 1, "split", "string as a separator")?;
+    let parts: Vec<Val> = if sep.is_empty() {
+        s.chars()
+            .map(|c| Rc::new(ValType::Str(Str::new(c.to_string()))))
+            .collect()
+    } else {
+        s.split(sep)
+            .map(|p| Rc::new(ValType::Str(Str::new(p.to_owned()))))
+            .collect()
+    };
+    Ok(Rc::new(ValType::Sexpr(Sexpr::new(parts))))
+}
+
+pub fn string_join(val: Sexpr, _: EnvRef) -> Result<Val, ASTError> {
+    check_len(&val, 2, "string-join")?;
+    let parts = match &*val.val[0] {
+        ValType::Sexpr(v) => v,
+        _ => return Err(type_error("string-join", "list as a first arg", Some(&val.val[0]))),
+    };
+    let sep = get_str(&val, 1, "string-join", "string as a separator")?;
+    let mut ret = Vec::new();
+    for i in 0..parts.val.len() {
+        ret.push(get_str(parts, i, "string-join", "list of strings")?);
+    }
+    new_str(ret.join(sep))
+}
+
+pub fn upcase(val: Sexpr, _: EnvRef) -> Result<Val, ASTError> {
+    check_len(&val, 1, "upcase")?;
+    new_str(get_str(&val, 0, "upcase", "string")?.to_uppercase())
+}
+
+pub fn downcase(val: Sexpr, _: EnvRef) -> Result<Val, ASTError> {
+    check_len(&val, 1, "downcase")?;
+    new_str(get_str(&val, 0, "downcase", "string")?.to_lowercase())
+}
+
+pub fn string_to_symbol(val: Sexpr, _: EnvRef) -> Result<Val, ASTError> {
+    check_len(&val, 1, "string->symbol")?;
+    let s = get_str(&val, 0, "string->symbol", "string")?;
+    Ok(Rc::new(ValType::Symbol(Symbol::new(s.to_owned()))))
+}
+
+pub fn number_to_string(val: Sexpr, _: EnvRef) -> Result<Val, ASTError> {
+    check_len(&val, 1, "number->string")?;
+    new_str(get_num(&val, 0, "number->string", "number")?.to_string())
+}
+
+fn get_fun(val: &Sexpr, i: usize, func: &str, expected: &'static str) -> Result<Val, ASTError> {
+    match val.val.get(i).map(|x| &**x) {
+        Some(ValType::Function(FuncType::Special(_))) => Err(eval_error(func, "special forms can't be passed around")),
+        Some(ValType::Function(_)) => Ok(Rc::clone(&val.val[i])),
+        _ => Err(type_error(func, expected, val.val.get(i))),
+    }
+}
+
+fn apply(fun: &Val, args: Vec<Val>, env: EnvRef) -> Result<Val, ASTError> {
+    ast::apply(fun, Sexpr::new(args), env)
+}
+
+// (partial f a b) is a function that calls f with a and b prepended to
+// whatever args it gets
+pub fn partial(val: Sexpr, _: EnvRef) -> Result<Val, ASTError> {
+    let fun = get_fun(&val, 0, "partial", "function as a first arg")?;
+    let bound: Vec<Val> = val.val[1..].to_vec();
+    Ok(FuncType::new_closure(
+        Box::new(move |val, env| {
+            let mut args = bound.clone();
+            args.extend(val.val);
+            apply(&fun, args, env)
+        }),
+        "partial",
+    ))
+}
+
+fn curried(fun: Val, arity: usize, bound: Vec<Val>) -> Val {
+    FuncType::new_closure(
+        Box::new(move |val, env| {
+            let mut args = bound.clone();
+            args.extend(val.val);
+            if args.len() >= arity {
+                apply(&fun, args, env)
+            } else {
+                Ok(curried(Rc::clone(&fun), arity, args))
+            }
+        }),
+        "curry",
+    )
+}
+
+// (curry f) collects args over several calls until f's required params are
+// all there. Builtins don't declare their arity, it has to be passed
+// explicitly: (curry + 3).
+pub fn curry(val: Sexpr, _: EnvRef) -> Result<Val, ASTError> {
+    if val.val.len() != 1 && val.val.len() != 2 {
+        return Err(arity_error("curry", "1 to 2", val.val.len()));
+    }
+    let fun = get_fun(&val, 0, "curry", "function as a first arg")?;
+    let arity = match (&*fun, val.val.len()) {
+        (_, 2) => get_int(&val, 1, "curry", "integer as an arity")?,
+        (ValType::Function(FuncType::Lambda(l)), _) => l.min_arity() as i128,
+        _ => return Err(eval_error("curry", "arity of a builtin must be given")),
+    };
+    if arity < 0 {
+        return Err(eval_error("curry", "arity must not be negative"));
+    }
+    Ok(curried(fun, arity as usize, Vec::new()))
+}
+
+// A chain of pairs ending in a proper list (see set-cdr!) is a list too
+fn get_list<'a>(val: &'a Sexpr, i: usize, func: &str, expected: &'static str) -> Result<Cow<'a, [Val]>, ASTError> {
+    match val.val.get(i).map(|x| &**x) {
+        Some(ValType::Sexpr(v)) => Ok(Cow::Borrowed(&v.val)),
+        Some(ValType::Pair(p)) => match p.items() {
+            (items, None) => Ok(Cow::Owned(items)),
+            _ => Err(type_error(func, expected, val.val.get(i))),
+        },
+        _ => Err(type_error(func, expected, val.val.get(i))),
+    }
+}
+
+fn new_list(val: Vec<Val>) -> Result<Val, ASTError> {
+    Ok(Rc::new(ValType::Sexpr(Sexpr::new(val))))
+}
+
+pub fn list(val: Sexpr, _: EnvRef) -> Result<Val, ASTError> {
+    new_list(val.val)
+}
+
+// Returns a list with the first element only, see nth for the element
+pub fn head(val: Sexpr, _: EnvRef) -> Result<Val, ASTError> {
+    check_len(&val, 1, "head")?;
+    match &*get_list(&val, 0, "head", "list")? {
+        [] => Err(eval_error("head", "empty list")),
+        l => new_list(l[..1].to_vec()),
+    }
+}
+
+pub fn tail(val: Sexpr, _: EnvRef) -> Result<Val, ASTError> {
+    check_len(&val, 1, "tail")?;
+    match &*get_list(&val, 0, "tail", "list")? {
+        [] => Err(eval_error("tail", "empty list")),
+        l => new_list(l[1..].to_vec()),
+    }
+}
+
+pub fn init(val: Sexpr, _: EnvRef) -> Result<Val, ASTError> {
+    check_len(&val, 1, "init")?;
+    match &*get_list(&val, 0, "init", "list")? {
+        [] => Err(eval_error("init", "empty list")),
+        l => new_list(l[..l.len() - 1].to_vec()),
+    }
+}
+
+pub fn join(val: Sexpr, _: EnvRef) -> Result<Val, ASTError> {
+    let mut ret = Vec::new();
+    for i in 0..val.val.len() {
+        ret.extend_from_slice(&get_list(&val, i, "join", "lists")?);
+    }
+    new_list(ret)
+}
+
+// Consing onto a list gives a list, onto anything else a pair
+pub fn cons(val: Sexpr, _: EnvRef) -> Result<Val, ASTError> {
+    check_len(&val, 2, "cons")?;
+    Ok(Rc::new(Pair::new_list(vec![Rc::clone(&val.val[0])], Rc::clone(&val.val[1]))))
+}
+
+pub fn car(val: Sexpr, _: EnvRef) -> Result<Val, ASTError> {
+    check_len(&val, 1, "car")?;
+    match &*val.val[0] {
+        ValType::Pair(p) => Ok(Rc::clone(&p.car.borrow())),
+        ValType::Sexpr(v) if !v.val.is_empty() => Ok(Rc::clone(&v.val[0])),
+        _ => Err(type_error("car", "pair or non-empty list", Some(&val.val[0]))),
+    }
+}
+
+pub fn cdr(val: Sexpr, _: EnvRef) -> Result<Val, ASTError> {
+    check_len(&val, 1, "cdr")?;
+    match &*val.val[0] {
+        ValType::Pair(p) => Ok(Rc::clone(&p.cdr.borrow())),
+        ValType::Sexpr(v) if !v.val.is_empty() => new_list(v.val[1..].to_vec()),
+        _ => Err(type_error("cdr", "pair or non-empty list", Some(&val.val[0]))),
+    }
+}
+
+// Lists are immutable, only pairs can be modified in place
+pub fn set_car(val: Sexpr, _: EnvRef) -> Result<Val, ASTError> {
+    check_len(&val, 2, "set-car!")?;
+    match &*val.val[0] {
+        ValType::Pair(p) => {
+            *p.car.borrow_mut() = Rc::clone(&val.val[1]);
+            Ok(Rc::new(ValType::Nil))
+        }
+        _ => Err(type_error("set-car!", "pair", Some(&val.val[0]))),
+    }
+}
+
+pub fn set_cdr(val: Sexpr, _: EnvRef) -> Result<Val, ASTError> {
+    check_len(&val, 2, "set-cdr!")?;
+    match &*val.val[0] {
+        ValType::Pair(p) => {
+            *p.cdr.borrow_mut() = Rc::clone(&val.val[1]);
+            Ok(Rc::new(ValType::Nil))
+        }
+        _ => Err(type_error("set-cdr!", "pair", Some(&val.val[0]))),
+    }
+}
+
+pub fn is_pair(val: Sexpr, _: EnvRef) -> Result<Val, ASTError> {
+    check_len(&val, 1, "pair?")?;
+    let ret = match &*val.val[0] {
+        ValType::Pair(_) => true,
+        ValType::Sexpr(v) => !v.val.is_empty(),
+        _ => false,
+    };
+    Ok(Rc::new(ValType::Bool(ret)))
+}
+
+// (assoc key alist) finds the first pair in alist whose car is equal? to
+// key, #f if there is none
+pub fn assoc(val: Sexpr, _: EnvRef) -> Result<Val, ASTError> {
+    check_len(&val, 2, "assoc")?;
+    let alist = get_list(&val, 1, "assoc", "list as a second arg")?;
+    for entry in alist.iter() {
+        let key = match &**entry {
+            ValType::Pair(p) => Rc::clone(&p.car.borrow()),
+            ValType::Sexpr(v) if !v.val.is_empty() => Rc::clone(&v.val[0]),
+            _ => return Err(type_error("assoc", "list of pairs", Some(entry))),
+        };
+        if equal(&key, &val.val[0]) {
+            return Ok(Rc::clone(entry));
+        }
+    }
+    Ok(Rc::new(ValType::Bool(false)))
+}
+
+pub fn len(val: Sexpr, _: EnvRef) -> Result<Val, ASTError> {
+    check_len(&val, 1, "len")?;
+    new_int(get_list(&val, 0, "len", "list")?.len())
+}
+
+pub fn nth(val: Sexpr, _: EnvRef) -> Result<Val, ASTError> {
+    check_len(&val, 2, "nth")?;
+    let l = get_list(&val, 0, "nth", "list as a first arg")?;
+    let i = get_int(&val, 1, "nth", "integer as an index")?;
+    match usize::try_from(i).ok().and_then(|i| l.get(i)) {
+        Some(v) => Ok(Rc::clone(v)),
+        None => Err(eval_error("nth", "index out of range")),
+    }
+}
+
+// (error "msg") or (error value) aborts evaluation with a user error
+pub fn error(val: Sexpr, _: EnvRef) -> Result<Val, ASTError> {
+    check_len(&val, 1, "error")?;
+    Err(ASTError {
+        error: ErrorKind::UserError(Rc::clone(&val.val[0])),
+        span: None,
+        backtrace: None,
+    })
+}
 
+// (display "a") prints a, (write "a") prints "a". Neither adds a newline.
+pub fn display(val: Sexpr, _: EnvRef) -> Result<Val, ASTError> {
+    check_len(&val, 1, "display")?;
+    print!("{}", printer::print(&val.val[0], Style::Display));
+    Ok(Rc::new(ValType::Nil))
+}
 
+pub fn write(val: Sexpr, _: EnvRef) -> Result<Val, ASTError> {
+    check_len(&val, 1, "write")?;
+    print!("{}", printer::print(&val.val[0], Style::Write));
+    Ok(Rc::new(ValType::Nil))
 }
 
+// Evaluates a quoted list as code in the caller's environment
+pub fn eval(val: Sexpr, env: EnvRef) -> Result<Val, ASTError> {
+    check_len(&val, 1, "eval")?;
+    val.val[0].eval(env)
+}
+
+// Evaluates all forms but the last one and leaves the last one, which is in
+// tail position, to the evaluator. Nil if there are no forms.
+fn eval_body(body: &[Val], env: EnvRef) -> Result<Trampoline, ASTError> {
+    match body.split_last() {
+        Some((last, init)) => {
+            for v in init {
+                v.eval(Rc::clone(&env))?;
+            }
+            Ok(Trampoline::Eval(Rc::clone(last), env))
+        }
+        None => Ok(Trampoline::Done(Rc::new(ValType::Nil))),
+    }
+}
+
+pub fn special_if(val: Sexpr, env: EnvRef) -> Result<Trampoline, ASTError> {
+    if val.val.len() != 2 && val.val.len() != 3 {
+        return Err(arity_error("if", "2 to 3", val.val.len()));
+    }
+    if val.val[0].eval(Rc::clone(&env))?.is_truthy() {
+        Ok(Trampoline::Eval(Rc::clone(&val.val[1]), env))
+    } else {
+        eval_body(&val.val[2..], env)
+    }
+}
+
+pub fn special_cond(val: Sexpr, env: EnvRef) -> Result<Trampoline, ASTError> {
+    for clause in val.val.iter() {
+        let clause = match &**clause {
+            ValType::Sexpr(v) if !v.val.is_empty() => v,
+            _ => return Err(type_error("cond", "non-empty list as a clause", Some(clause))),
+        };
+        let test = match &*clause.val[0] {
+            ValType::Symbol(s) if s.val == "else" => return eval_body(&clause.val[1..], env),
+            _ => clause.val[0].eval(Rc::clone(&env))?,
+        };
+        if test.is_truthy() {
+            // A clause without body yields its test value, like in Scheme
+            if clause.val.len() == 1 {
+                return Ok(Trampoline::Done(test));
+            }
+            return eval_body(&clause.val[1..], env);
+        }
+    }
+    Ok(Trampoline::Done(Rc::new(ValType::Nil)))
+}
+
+pub fn special_when(val: Sexpr, env: EnvRef) -> Result<Trampoline, ASTError> {
+    if val.val.is_empty() {
+        return Err(arity_error("when", "at least 1", 0));
+    }
+    if val.val[0].eval(Rc::clone(&env))?.is_truthy() {
+        eval_body(&val.val[1..], env)
+    } else {
+        Ok(Trampoline::Done(Rc::new(ValType::Nil)))
+    }
+}
+
+pub fn special_unless(val: Sexpr, env: EnvRef) -> Result<Trampoline, ASTError> {
+    if val.val.is_empty() {
+        return Err(arity_error("unless", "at least 1", 0));
+    }
+    if val.val[0].eval(Rc::clone(&env))?.is_truthy() {
+        Ok(Trampoline::Done(Rc::new(ValType::Nil)))
+    } else {
+        eval_body(&val.val[1..], env)
+    }
+}
+
+// Both return the value that decided the outcome rather than a flag, the
+// last form is in tail position
+pub fn special_and(val: Sexpr, env: EnvRef) -> Result<Trampoline, ASTError> {
+    let (last, init) = match val.val.split_last() {
+        Some(v) => v,
+        None => return Ok(Trampoline::Done(Rc::new(ValType::Bool(true)))),
+    };
+    for v in init {
+        let ret = v.eval(Rc::clone(&env))?;
+        if !ret.is_truthy() {
+            return Ok(Trampoline::Done(ret));
+        }
+    }
+    Ok(Trampoline::Eval(Rc::clone(last), env))
+}
+
+pub fn special_or(val: Sexpr, env: EnvRef) -> Result<Trampoline, ASTError> {
+    let (last, init) = match val.val.split_last() {
+        Some(v) => v,
+        None => return Ok(Trampoline::Done(Rc::new(ValType::Nil))),
+    };
+    for v in init {
+        let ret = v.eval(Rc::clone(&env))?;
+        if ret.is_truthy() {
+            return Ok(Trampoline::Done(ret));
+        }
+    }
+    Ok(Trampoline::Eval(Rc::clone(last), env))
+}
+
+pub fn special_begin(val: Sexpr, env: EnvRef) -> Result<Trampoline, ASTError> {
+    eval_body(&val.val, env)
+}
+
+// Chained comparison: (< a b c) holds if every adjacent pair is ordered.
+// Numbers are compared across the numeric tower, strings lexicographically.
+pub fn cmp(
+    name: &'static str,
+    pred: fn(Ordering) -> bool,
+) -> Box<dyn Fn(Sexpr, EnvRef) -> Result<Val, ASTError>> {
+    Box::new(move |val, _| {
+        let mut ret = true;
+        for pair in val.val.windows(2) {
+            let ord = match (&*pair[0], &*pair[1]) {
+                (ValType::Number(a), ValType::Number(b)) => a.num_cmp(b),
+                (ValType::Str(a), ValType::Str(b)) => Some(a.val.cmp(&b.val)),
+                _ if name == "=" => Some(if equal(&pair[0], &pair[1]) {
+                    Ordering::Equal
+                } else {
+                    Ordering::Less
+                }),
+                (ValType::Number(_), _) => return Err(type_error(name, "number", Some(&pair[1]))),
+                (ValType::Str(_), _) => return Err(type_error(name, "string", Some(&pair[1]))),
+                _ => return Err(type_error(name, "numbers or strings", Some(&pair[0]))),
+            };
+            // NaN is unordered, so every comparison with it is false
+            ret = ret && ord.is_some_and(pred);
+        }
+        Ok(Rc::new(ValType::Bool(ret)))
+    })
+}
+
+pub fn not(val: Sexpr, _: EnvRef) -> Result<Val, ASTError> {
+    check_len(&val, 1, "not")?;
+    Ok(Rc::new(ValType::Bool(!val.val[0].is_truthy())))
+}
+
+// Atoms are compared by value, everything else (lists, strings, functions)
+// only by identity
+fn eqv(a: &Val, b: &Val) -> bool {
+    if Rc::ptr_eq(a, b) {
+        return true;
+    }
+    match (&**a, &**b) {
+        (ValType::Number(_), ValType::Number(_))
+        | (ValType::Symbol(_), ValType::Symbol(_))
+        | (ValType::Bool(_), ValType::Bool(_))
+        | (ValType::Nil, ValType::Nil) => a == b,
+        _ => false,
+    }
+}
+
+// Deep structural equality. Numbers have to be of the same exactness, so
+// (equal? 1 1.0) is false while (= 1 1.0) is true.
+pub fn equal(a: &Val, b: &Val) -> bool {
+    equal_in(a, b, &mut HashSet::new())
+}
+
+// Pairs already being compared are taken to be equal, which is what ends
+// the comparison of cyclic structures
+fn equal_in(a: &Val, b: &Val, seen: &mut HashSet<(*const Pair, *const Pair)>) -> bool {
+    match (&**a, &**b) {
+        (ValType::Sexpr(x), ValType::Sexpr(y)) => {
+            x.val.len() == y.val.len() && x.val.iter().zip(y.val.iter()).all(|(x, y)| equal_in(x, y, seen))
+        }
+        (ValType::Pair(x), ValType::Pair(y)) => {
+            if !seen.insert((x as *const Pair, y as *const Pair)) {
+                return true;
+            }
+            equal_in(&x.car.borrow(), &y.car.borrow(), seen) && equal_in(&x.cdr.borrow(), &y.cdr.borrow(), seen)
+        }
+        // a chain of pairs ending in a proper list equals the same list
+        (ValType::Pair(p), ValType::Sexpr(l)) | (ValType::Sexpr(l), ValType::Pair(p)) => match p.items() {
+            (items, None) => {
+                items.len() == l.val.len() && items.iter().zip(l.val.iter()).all(|(x, y)| equal_in(x, y, seen))
+            }
+            _ => false,
+        },
+        (ValType::Str(x), ValType::Str(y)) => x == y,
+        _ => eqv(a, b),
+    }
+}
+
+pub fn is_eq(val: Sexpr, _: EnvRef) -> Result<Val, ASTError> {
+    check_len(&val, 2, "eq?")?;
+    Ok(Rc::new(ValType::Bool(eqv(&val.val[0], &val.val[1]))))
+}
+
+pub fn is_equal(val: Sexpr, _: EnvRef) -> Result<Val, ASTError> {
+    check_len(&val, 2, "equal?")?;
+    Ok(Rc::new(ValType::Bool(equal(&val.val[0], &val.val[1]))))
+}
+
+// One line of documentation per builtin, shown by :doc in the REPL
+pub fn doc(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "+" => "(+ n ...) sum of the numbers, 0 without any",
+        "-" => "(- n ...) subtracts the rest from the first, (- n) negates",
+        "*" => "(* n ...) product of the numbers, 1 without any",
+        "/" => "(/ n ...) divides the first by the rest, (/ n) is 1/n",
+        "=" => "(= a b ...) true if all numbers are equal, 1 and 1.0 included",
+        "<" => "(< a b ...) true if every number or string is less than the next",
+        ">" => "(> a b ...) true if every number or string is greater than the next",
+        "<=" => "(<= a b ...) true if no number or string is greater than the next",
+        ">=" => "(>= a b ...) true if no number or string is less than the next",
+        "not" => "(not v) #t for #f and nil, #f for anything else",
+        "eq?" => "(eq? a b) atoms by value, everything else by identity",
+        "equal?" => "(equal? a b) deep structural equality",
+        "quotient" => "(quotient a b) integer division, rounding toward zero",
+        "mod" => "(mod a b) remainder with the sign of b",
+        "expt" => "(expt base exp) base raised to exp",
+        "gcd" => "(gcd n ...) greatest common divisor of the integers",
+        "setq" => "(setq '(name ...) value ...) binds names in the current env",
+        "set!" => "(set! '(name ...) value ...) rebinds existing names",
+        "\\" => "(\\ '(params) '(body)) makes a lambda",
+        "list" => "(list v ...) list of the args",
+        "head" => "(head l) list with the first element of l only",
+        "tail" => "(tail l) l without its first element",
+        "init" => "(init l) l without its last element",
+        "join" => "(join l ...) the lists appended",
+        "cons" => "(cons a b) list with a in front of list b, or the pair (a . b)",
+        "error" => "(error v) aborts evaluation with v as the message",
+        "car" => "(car p) first element of a pair or list",
+        "cdr" => "(cdr p) rest of a pair or list",
+        "set-car!" => "(set-car! p v) replaces the car of pair p",
+        "set-cdr!" => "(set-cdr! p v) replaces the cdr of pair p",
+        "pair?" => "(pair? v) true for pairs and non-empty lists",
+        "assoc" => "(assoc key alist) first entry of alist whose car is key, #f if none",
+        "len" => "(len l) number of elements in l",
+        "nth" => "(nth l i) element at index i of l, from 0",
+        "eval" => "(eval '(expr)) evaluates a quoted list as code",
+        "partial" => "(partial f a ...) f with a ... bound as its first args",
+        "curry" => "(curry f [arity]) f taking its args over several calls",
+        "if" => "(if test then [else]) then if test is true, else otherwise",
+        "cond" => "(cond (test body ...) ... (else body ...)) body of the first true test",
+        "when" => "(when test body ...) body if test is true",
+        "unless" => "(unless test body ...) body if test is false",
+        "and" => "(and v ...) first false value, or the last one",
+        "or" => "(or v ...) first true value, or the last one",
+        "begin" | "do" => "(begin form ...) evaluates the forms, returns the last one",
+        "concat" => "(concat s ...) the strings joined together",
+        "string-length" => "(string-length s) number of chars in s",
+        "substring" => "(substring s start [end]) chars of s from start up to end",
+        "index-of" => "(index-of s sub) char index of sub in s, -1 if it isn't there",
+        "split" => "(split s sep) list of the parts of s between seps",
+        "string-join" => "(string-join l sep) the strings in l joined with sep",
+        "upcase" => "(upcase s) s in upper case",
+        "downcase" => "(downcase s) s in lower case",
+        "string->symbol" => "(string->symbol s) symbol named s",
+        "number->string" => "(number->string n) n as a string",
+        "display" => "(display v) prints v, strings without quotes",
+        "write" => "(write v) prints v so that it reads back",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{boolean, err, eval, list, num, string, Session};
+
+    #[test]
+    fn eval_numeric_tower_works() {
+        assert_eq!(*eval("(+ 1 2.5)"), ValType::Number(Number::float(3.5)));
+        assert_eq!(*eval("(/ 1 3)"), ValType::Number(Number::ratio(1, 3).unwrap()));
+        assert_eq!(eval("(- 10 4 3)"), num(3));
+        assert_eq!(eval("(- 5)"), num(-5));
+        assert_eq!(*eval("(* 2 -1/4)"), ValType::Number(Number::ratio(-1, 2).unwrap()));
+        assert_eq!(*eval("(* 1e3 -2.5e-3)"), ValType::Number(Number::float(-2.5)));
+        assert_eq!(*eval("(/ 1.0 0)"), ValType::Number(Number::float(f64::INFINITY)));
+        assert_eq!(*eval("(+ -inf.0 1)"), ValType::Number(Number::float(f64::NEG_INFINITY)));
+        assert_eq!(*eval("(+ .5 1)"), ValType::Number(Number::float(1.5)));
+        assert_eq!(*eval("(- -.5)"), ValType::Number(Number::float(0.5)));
+    }
+
+    #[test]
+    fn eval_arithmetic_errors_works() {
+        assert!(matches!(err("(/ 1 0)").error, ErrorKind::DivisionByZero));
+        assert!(matches!(err("(/ 1/2 0)").error, ErrorKind::DivisionByZero));
+        assert!(matches!(err("(quotient 1 0)").error, ErrorKind::DivisionByZero));
+        assert!(matches!(err("(mod 1 0)").error, ErrorKind::DivisionByZero));
+        assert!(matches!(err("(/ 1 (expt 2 200))").error, ErrorKind::Overflow));
+        assert!(matches!(err("(expt 3 100000000)").error, ErrorKind::Overflow));
+        assert_eq!(err("(quotient 7 2.0)").to_string(), "quotient -- expected integers, got float");
+        assert_eq!(err("(mod 1/2 2)").to_string(), "mod -- expected integers, got ratio");
+        assert_eq!(err("(gcd 4 \"a\")").to_string(), "gcd -- expected integers, got string");
+    }
+
+    #[test]
+    fn eval_bignum_works() {
+        let num = |input| eval(input).to_string();
+        assert_eq!(
+            num("(* 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30)"),
+            "265252859812191058636308480000000"
+        );
+        assert_eq!(
+            num("(/ -170141183460469231731687303715884105728 -1)"),
+            "170141183460469231731687303715884105728"
+        );
+        assert_eq!(num("(- 100000000000000000000000000000000000000000 1)"), "99999999999999999999999999999999999999999");
+        assert_eq!(num("(quotient (expt 10 40) (expt 10 38))"), "100");
+        assert_eq!(num("(mod (- (expt 2 128)) 7)"), "3");
+        assert_eq!(num("(gcd (expt 2 200) (expt 6 100) 0)"), "1267650600228229401496703205376");
+        assert_eq!(num("(expt 2 -3)"), "1/8");
+        assert_eq!(num("(expt 1 (expt 10 40))"), "1");
+        assert_eq!(num("(expt 0 (expt 10 40))"), "0");
+        assert_eq!(num("(expt -1 (+ (expt 10 40) 1))"), "-1");
+        assert_eq!(num("(expt 2.0 0.5)"), "1.4142135623730951");
+    }
+
+    #[test]
+    fn eval_special_forms_works() {
+        let s = Session::default();
+        assert_eq!(s.eval("(if #t 2 3)"), num(2));
+        assert_eq!(s.eval("(if #f 2 3)"), num(3));
+        assert_eq!(s.eval("(if 0 2 3)"), num(2));
+        assert_eq!(s.eval("(if #f 2)"), Rc::new(ValType::Nil));
+        assert_eq!(s.eval("(if #t 2 (/ 1 0))"), num(2));
+        assert_eq!(s.eval("(cond (#f 1) ((= 2 3) 2) (else 3 4))"), num(4));
+        assert_eq!(s.eval("(cond (#f 1) ((+ 2 3)))"), num(5));
+        assert_eq!(s.eval("(when #t 2 3)"), num(3));
+        assert_eq!(s.eval("(unless #t (/ 1 0))"), Rc::new(ValType::Nil));
+        assert_eq!(s.eval("(and 1 #f (/ 1 0))"), boolean(false));
+        assert_eq!(s.eval("(and)"), boolean(true));
+        assert_eq!(s.eval("(or #f 7 (/ 1 0))"), num(7));
+        assert_eq!(s.eval("(begin 1 2 3)"), num(3));
+        assert_eq!(s.eval("(do)"), Rc::new(ValType::Nil));
+
+        s.eval("(setq '(fact) (\\ '(n) '(if (= n 0) 1 (* n (fact (- n 1))))))");
+        assert_eq!(s.eval("(fact 20)"), num(2432902008176640000));
+    }
+
+    #[test]
+    fn eval_partial_application_works() {
+        let s = Session::default();
+        s.eval("(setq '(sub3) (\\ '(a b c) '(- a b c)))");
+        match s.err("(sub3 10 1)").error {
+            ErrorKind::Arity { func, expected, got, .. } => {
+                assert_eq!((func.as_str(), expected.as_str(), got), ("sub3", "3", 2));
+            }
+            e => panic!("unexpected error {:?}", e),
+        }
+        assert_eq!(s.eval("((partial sub3 10 1) 2)"), num(7));
+        assert_eq!(s.eval("((partial sub3 10) 1 2)"), num(7));
+        assert_eq!(s.eval("((partial + 1 2) 3 4)"), num(10));
+        assert_eq!(s.eval("((partial (partial - 10) 1) 2)"), num(7));
+        assert_eq!(s.eval("(((curry sub3) 10) 1 2)"), num(7));
+        assert_eq!(s.eval("((((curry sub3) 10) 1) 2)"), num(7));
+        assert_eq!(s.eval("(((curry * 2) 3) 4)"), num(12));
+        assert!(s.run("(curry +)").is_err());
+        assert!(s.run("(partial if 1)").is_err());
+        assert!(s.run("(partial 1 2)").is_err());
+    }
+
+    #[test]
+    fn eval_list_builtins_works() {
+        let nums = |v: Vec<i128>| list(v.into_iter().map(num).collect());
+        assert_eq!(eval("{}"), nums(vec![]));
+        assert_eq!(eval("(list 1 (+ 1 1) 3)"), nums(vec![1, 2, 3]));
+        assert_eq!(eval("(head {1 2 3})"), nums(vec![1]));
+        assert_eq!(eval("(tail {1 2 3})"), nums(vec![2, 3]));
+        assert_eq!(eval("(init {1 2 3})"), nums(vec![1, 2]));
+        assert_eq!(eval("(join {1} {} {2 3})"), nums(vec![1, 2, 3]));
+        assert_eq!(eval("(cons 0 {1 2})"), nums(vec![0, 1, 2]));
+        assert_eq!(eval("(len {1 2 3})"), num(3));
+        assert_eq!(eval("(nth {1 2 3} 2)"), num(3));
+        assert_eq!(eval("(eval {+ 1 2})"), num(3));
+        assert_eq!(eval("(eval (join {*} (tail {0 2 3})))"), num(6));
+        assert_eq!(eval("(eval (head {(+ 1 2) 4}))"), num(3));
+
+        for input in ["(head {})", "(tail 1)", "(nth {1} 1)"] {
+            assert!(Session::default().run(input).is_err(), "{}", input);
+        }
+        assert_eq!(err("(nth {1} (expt 2 200))").to_string(), "nth -- index out of range");
+        assert_eq!(err("(nth {1} (- (expt 2 200)))").to_string(), "nth -- index out of range");
+        assert_eq!(err("(nth {1} 0.5)").to_string(), "nth -- expected integer as an index, got float");
+    }
+
+    #[test]
+    fn eval_pairs_works() {
+        let s = Session::default();
+        assert_eq!(s.show("(cons 1 2)"), "(1 . 2)");
+        assert_eq!(s.show("(cons 0 (cons 1 2))"), "(0 1 . 2)");
+        assert_eq!(s.show("(cons 1 {2})"), "(1 2)");
+        assert_eq!(s.eval("(car '(1 . 2))"), num(1));
+        assert_eq!(s.eval("(cdr '(1 . 2))"), num(2));
+        assert_eq!(s.eval("(car {1 2})"), num(1));
+        assert_eq!(s.show("(cdr {1 2})"), "(2)");
+        assert_eq!(s.show("(cdr '(1 2 . 3))"), "(2 . 3)");
+        assert_eq!(s.eval("(pair? '(1 . 2))"), boolean(true));
+        assert_eq!(s.eval("(pair? {})"), boolean(false));
+
+        s.eval("(setq '(p) (cons 1 2))");
+        s.eval("(set-car! p 10)");
+        s.eval("(set-cdr! p {20 30})");
+        assert_eq!(s.show("p"), "(10 20 30)");
+        // a pair whose cdr is a proper list is a list to list builtins
+        assert_eq!(s.eval("(equal? p '(10 20 30))"), boolean(true));
+        assert_eq!(s.eval("(equal? {10 20 30} p)"), boolean(true));
+        assert_eq!(s.eval("(equal? p '(10 20))"), boolean(false));
+        assert_eq!(s.eval("(len p)"), num(3));
+        assert_eq!(s.show("(tail p)"), "(20 30)");
+        assert_eq!(s.eval("(nth p 2)"), num(30));
+        s.eval("(set-cdr! p {})");
+        assert_eq!(s.eval("(equal? p '(10))"), boolean(true));
+        assert_eq!(s.eval("(len p)"), num(1));
+        assert!(s.run("(len (cons 1 2))").is_err());
+
+        s.eval("(setq '(alist) '((a . 1) (b . 2) (\"c\" . 3)))");
+        assert_eq!(s.eval("(cdr (assoc 'b alist))"), num(2));
+        assert_eq!(s.eval("(cdr (assoc \"c\" alist))"), num(3));
+        assert_eq!(s.eval("(assoc 'z alist)"), boolean(false));
+        assert_eq!(s.eval("(equal? '(1 . 2) (cons 1 2))"), boolean(true));
+
+        // cycles built with set-cdr! end walks and comparisons
+        s.eval("(setq '(c d) (cons 1 2) (cons 1 (cons 1 2)))");
+        s.eval("(set-cdr! c c)");
+        s.eval("(set-cdr! (cdr d) d)");
+        assert_eq!(s.eval("(equal? c c)"), boolean(true));
+        assert_eq!(s.eval("(equal? c d)"), boolean(true));
+        assert_eq!(s.eval("(equal? c (cons 1 c))"), boolean(true));
+        assert_eq!(s.eval("(equal? c (cons 2 c))"), boolean(false));
+        assert_eq!(s.eval("(car (cdr (cdr c)))"), num(1));
+        assert_eq!(s.eval("(equal? c '(1 1))"), boolean(false));
+        assert!(s.run("(len c)").is_err());
+
+        assert!(s.run("(car {})").is_err());
+        assert!(s.run("(set-car! {1 2} 3)").is_err());
+    }
+
+    #[test]
+    fn eval_comparison_works() {
+        let (t, f) = (boolean(true), boolean(false));
+        assert_eq!(eval("(= 1 1.0 2/2)"), t);
+        assert_eq!(eval("(= 1 2)"), f);
+        assert_eq!(eval("(< 1 3/2 2.5 (expt 10 40))"), t);
+        assert_eq!(eval("(< 1 3 2)"), f);
+        assert_eq!(eval("(> 3 2 1)"), t);
+        assert_eq!(eval("(<= 1 1 2)"), t);
+        assert_eq!(eval("(>= 1 2)"), f);
+        assert_eq!(eval("(< +nan.0 1)"), f);
+        assert_eq!(eval(r#"(< "abc" "abd")"#), t);
+        assert_eq!(eval(r#"(= "abc" "abc")"#), t);
+        assert_eq!(eval("(not #f)"), t);
+        assert_eq!(eval("(not 0)"), f);
+        assert_eq!(eval("(eq? 'a 'a)"), t);
+        assert_eq!(eval("(eq? '(1 2) '(1 2))"), f);
+        assert_eq!(eval("(equal? '(1 (2 \"x\") a) '(1 (2 \"x\") a))"), t);
+        assert_eq!(eval("(equal? '(1 2) '(1 2.0))"), f);
+        assert_eq!(eval("(equal? #t #t)"), t);
+        assert!(Session::default().run(r#"(< 1 "a")"#).is_err());
+    }
+
+    #[test]
+    fn eval_string_builtins_works() {
+        assert_eq!(eval(r#"(concat "foo" "bar" "")"#), string("foobar"));
+        assert_eq!(eval(r#"(string-length "λx")"#), num(2));
+        assert_eq!(eval(r#"(substring "hello" 1 3)"#), string("el"));
+        assert_eq!(eval(r#"(substring "hello" 3)"#), string("lo"));
+        assert_eq!(eval(r#"(index-of "hello" "l")"#), num(2));
+        assert_eq!(eval(r#"(index-of "hello" "z")"#), num(-1));
+        assert_eq!(eval(r#"(string-join (split "a,b,c" ",") "-")"#), string("a-b-c"));
+        assert_eq!(eval(r#"(upcase "abc")"#), string("ABC"));
+        assert_eq!(eval(r#"(downcase "ABC")"#), string("abc"));
+        assert_eq!(eval("(number->string (* 6 7))"), string("42"));
+        assert_eq!(*eval(r#"(string->symbol "foo")"#), ValType::Symbol(Symbol::new("foo".to_owned())));
+        assert!(Session::default().run(r#"(substring "abc" 2 5)"#).is_err());
+        assert_eq!(
+            err(r#"(substring "abc" 0 (expt 10 40))"#).to_string(),
+            "substring -- index out of range"
+        );
+    }
+}
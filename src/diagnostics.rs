@@ -0,0 +1,369 @@
+// rustc-style error reports:
+//
+//   error[E0204]: f -- expected 1 arg, got 2
+//    --> script.lisp:2:1
+//     |
+//   1 | (setq '(f) (\ '(x) '(+ x 1)))
+//     |                ------------ f defined here
+//   2 | (f 1 2)
+//     | ^^^^^^^ called with 2 args
+//
+use crate::ast::{ASTError, ErrorKind as AstErrorKind};
+use crate::parser::{ErrorKind as ParserErrorKind, ParserError};
+use crate::span::{self, Span};
+use crate::token::{ErrorKind as TokenizerErrorKind, TokenizerError};
+use std::env;
+use std::io::IsTerminal;
+
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+// Colors are used only on terminals and can be turned off with NO_COLOR
+pub fn use_color<T: IsTerminal>(stream: &T) -> bool {
+    stream.is_terminal() && env::var_os("NO_COLOR").is_none()
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub msg: String,
+    pub primary: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Diagnostic {
+    pub code: &'static str,
+    pub msg: String,
+    pub labels: Vec<Label>,
+    pub help: Vec<String>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(code: &'static str, msg: String) -> Diagnostic {
+        Diagnostic {
+            code,
+            msg,
+            labels: Vec::new(),
+            help: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    // Location of the error itself, rendered with ^^^
+    pub fn primary(mut self, span: Option<Span>, msg: &str) -> Diagnostic {
+        if let Some(span) = span {
+            self.labels.push(Label {
+                span,
+                msg: msg.to_owned(),
+                primary: true,
+            });
+        }
+        self
+    }
+
+    // Related location, rendered with ---
+    pub fn secondary(mut self, span: Option<Span>, msg: &str) -> Diagnostic {
+        if let Some(span) = span {
+            self.labels.push(Label {
+                span,
+                msg: msg.to_owned(),
+                primary: false,
+            });
+        }
+        self
+    }
+
+    pub fn help(mut self, msg: &str) -> Diagnostic {
+        self.help.push(msg.to_owned());
+        self
+    }
+
+    // Multi-line notes are fine, the lines are aligned after `note: `
+    pub fn note(mut self, msg: &str) -> Diagnostic {
+        self.notes.push(msg.to_owned());
+        self
+    }
+
+    // `name` is the file name shown after -->, `source` is the text the
+    // spans point into
+    pub fn render(&self, name: &str, source: &str, color: bool) -> String {
+        let paint = |s: &str, c: &str| {
+            if color {
+                format!("{}{}{}", c, s, RESET)
+            } else {
+                s.to_owned()
+            }
+        };
+
+        let mut labels: Vec<(&Label, (&str, String, usize))> = self
+            .labels
+            .iter()
+            .filter_map(|v| Some((v, span::line_at(source, v.span)?)))
+            .collect();
+        labels.sort_by_key(|(v, _)| (v.span.line, v.span.start));
+        let width = labels
+            .iter()
+            .map(|(v, _)| v.span.line.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let pad = " ".repeat(width);
+        let gutter = paint(&format!("{} |", pad), BLUE);
+
+        let mut ret = format!(
+            "{}{}",
+            paint(&format!("error[{}]", self.code), RED),
+            paint(&format!(": {}", self.msg), BOLD)
+        );
+        let location = match self.labels.iter().find(|v| v.primary) {
+            Some(v) => format!("{}:{}", name, v.span),
+            None => name.to_owned(),
+        };
+        ret += &format!("\n{}{} {}", pad, paint("-->", BLUE), location);
+
+        if !labels.is_empty() {
+            ret += &format!("\n{}", gutter);
+        }
+        let mut last_line = None;
+        for (label, (line, indent, len)) in labels.iter() {
+            // Labels on one line share the source line
+            if last_line != Some(label.span.line) {
+                let num = format!("{:>w$} |", label.span.line, w = width);
+                ret += &format!("\n{} {}", paint(&num, BLUE), line);
+                last_line = Some(label.span.line);
+            }
+            let (mark, c) = if label.primary { ("^", RED) } else { ("-", BLUE) };
+            let mut marks = mark.repeat(*len);
+            if !label.msg.is_empty() {
+                marks = format!("{} {}", marks, label.msg);
+            }
+            ret += &format!("\n{} {}{}", gutter, indent, paint(&marks, c));
+        }
+
+        let notes = self
+            .help
+            .iter()
+            .map(|v| ("help:", v))
+            .chain(self.notes.iter().map(|v| ("note:", v)));
+        if (!self.help.is_empty() || !self.notes.is_empty()) && !labels.is_empty() {
+            ret += &format!("\n{}", gutter);
+        }
+        for (kind, msg) in notes {
+            let indent = " ".repeat(width + kind.len() + 4);
+            ret += &format!("\n{} {} {}", pad, paint("=", BLUE), paint(kind, BOLD));
+            for (i, line) in msg.lines().enumerate() {
+                match i {
+                    0 => ret += &format!(" {}", line),
+                    _ => ret += &format!("\n{}{}", indent, line),
+                }
+            }
+        }
+        ret
+    }
+}
+
+impl From<&TokenizerError> for Diagnostic {
+    fn from(e: &TokenizerError) -> Self {
+        let span = Some(e.span);
+        match e.error {
+            TokenizerErrorKind::GeneralError => {
+                Diagnostic::new("E0001", e.to_string()).primary(span, "")
+            }
+            TokenizerErrorKind::UnterminatedLiteral => Diagnostic::new("E0002", e.to_string())
+                .primary(span, "string starts here")
+                .help("add a closing '\"'"),
+            TokenizerErrorKind::UnterminatedComment => Diagnostic::new("E0003", e.to_string())
+                .primary(span, "comment starts here")
+                .help("add a closing '|#', block comments nest"),
+            TokenizerErrorKind::MissingDatum => Diagnostic::new("E0004", e.to_string())
+                .primary(span, "nothing to comment out after this")
+                .help("'#;' comments out the expression right after it"),
+        }
+    }
+}
+
+impl From<&ParserError> for Diagnostic {
+    fn from(e: &ParserError) -> Self {
+        let span = Some(e.span);
+        let msg = e.error.to_string();
+        match &e.error {
+            ParserErrorKind::TokenizerError(e) => Diagnostic::from(e),
+            ParserErrorKind::ParserError => {
+                Diagnostic::new("E0101", msg).primary(span, "expected an expression")
+            }
+            ParserErrorKind::ParseSexprError => Diagnostic::new("E0102", msg)
+                .primary(span, "this list is never closed")
+                .help("add the missing closing paren"),
+            ParserErrorKind::NumberParseError => Diagnostic::new("E0103", msg)
+                .primary(span, "")
+                .help("numbers look like 42, -1.5e3, 1/3 or +inf.0"),
+            ParserErrorKind::StringParseError => Diagnostic::new("E0104", msg)
+                .primary(span, "")
+                .help(r#"supported escapes are \n \t \r \0 \" \\ and \u{...}"#),
+            ParserErrorKind::ExprParseError(t) => {
+                let ret = Diagnostic::new("E0105", msg).primary(span, "");
+                match t.as_str() {
+                    ")" | "}" => ret.help("this closes a list that was never opened"),
+                    "." => ret.help("'.' may only come before the last element of a list"),
+                    _ => ret,
+                }
+            }
+        }
+    }
+}
+
+impl From<&ASTError> for Diagnostic {
+    fn from(e: &ASTError) -> Self {
+        let msg = e.error.to_string();
+        let ret = match &e.error {
+            AstErrorKind::Eval { .. } => Diagnostic::new("E0200", msg).primary(e.span, ""),
+            AstErrorKind::UnboundSymbol(_) => Diagnostic::new("E0201", msg)
+                .primary(e.span, "not found in this scope")
+                .help("bind it first with (setq '(name) value)"),
+            AstErrorKind::DivisionByZero => Diagnostic::new("E0202", msg).primary(e.span, ""),
+            AstErrorKind::Overflow => Diagnostic::new("E0203", msg).primary(e.span, ""),
+            AstErrorKind::Arity {
+                func, got, defined, ..
+            } => Diagnostic::new("E0204", msg)
+                .primary(e.span, &format!("called with {} arg{}", got, if *got == 1 { "" } else { "s" }))
+                .secondary(defined.as_deref().copied(), &format!("{} defined here", func)),
+            AstErrorKind::TypeMismatch { got, .. } => {
+                Diagnostic::new("E0205", msg).primary(e.span, &format!("got {} here", got))
+            }
+            AstErrorKind::UserError(_) => Diagnostic::new("E0206", msg).primary(e.span, ""),
+        };
+        match e.backtrace.as_deref() {
+            // a lone call at the primary span adds nothing
+            Some(bt) if bt.omitted == 0 && bt.frames.len() == 1 && bt.frames[0].span == e.span => ret,
+            Some(bt) => {
+                let mut note = "backtrace, innermost call first:".to_owned();
+                for (i, frame) in bt.frames.iter().enumerate() {
+                    note += &format!("\n  {}: {}", i, frame);
+                }
+                if bt.omitted > 0 {
+                    note += &format!("\n  ... {} more", bt.omitted);
+                }
+                ret.note(&note)
+            }
+            None => ret,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::eval_program;
+    use crate::env::Env;
+    use crate::parser::Parser;
+    use std::rc::Rc;
+
+    fn report(input: &str) -> String {
+        let d = match Parser::new(input).parse_program() {
+            Ok(program) => {
+                let env = Rc::new(Env::new(None));
+                Diagnostic::from(&eval_program(&program, env).unwrap_err())
+            }
+            Err(e) => Diagnostic::from(&e),
+        };
+        d.render("t.lisp", input, false)
+    }
+
+    #[test]
+    fn diagnostics_render_works() {
+        assert_eq!(
+            report("(+ 1 2)\n(foo))"),
+            "error[E0105]: unexpected ')'
+ --> t.lisp:2:6
+  |
+2 | (foo))
+  |      ^
+  |
+  = help: this closes a list that was never opened"
+        );
+        assert_eq!(
+            report("(setq '(f) (\\ '(x) '(+ x 1)))\n\n\n\n\n\n\n\n\n\n(f 1 2)"),
+            "error[E0204]: f -- expected 1 arg, got 2
+  --> t.lisp:11:1
+   |
+ 1 | (setq '(f) (\\ '(x) '(+ x 1)))
+   |                ------------ f defined here
+11 | (f 1 2)
+   | ^^^^^^^ called with 2 args"
+        );
+        assert_eq!(
+            report("(+ 1 nope)"),
+            "error[E0201]: unbound symbol: nope
+ --> t.lisp:1:6
+  |
+1 | (+ 1 nope)
+  |      ^^^^ not found in this scope
+  |
+  = help: bind it first with (setq '(name) value)"
+        );
+        assert_eq!(
+            report("(setq '(f) (\\ '(x) '(car x)))\n(f {})"),
+            "error[E0205]: car -- expected pair or non-empty list, got empty list
+ --> t.lisp:1:21
+  |
+1 | (setq '(f) (\\ '(x) '(car x)))
+  |                     ^^^^^^^ got empty list here
+  |
+  = note: backtrace, innermost call first:
+            0: car at 1:21
+            1: f (x = ()) at 2:1"
+        );
+        // builtins have no definition site
+        assert_eq!(
+            Diagnostic::from(&ASTError {
+                error: AstErrorKind::DivisionByZero,
+                span: None,
+                backtrace: None,
+            })
+            .render("<repl>", "", false),
+            "error[E0202]: division by zero\n --> <repl>"
+        );
+    }
+
+    #[test]
+    fn diagnostics_spans_works() {
+        // the location and the underline of the primary label
+        let at = |input| {
+            let out = report(input);
+            let loc = out.lines().find_map(|l| l.trim_start().strip_prefix("--> ")).unwrap().to_owned();
+            let mark = out.lines().find(|l| l.contains('^')).unwrap().split("| ").nth(1).unwrap().trim_end().to_owned();
+            (loc, mark)
+        };
+        let pair = |loc: &str, mark: &str| (loc.to_owned(), mark.to_owned());
+        assert_eq!(at("(+ 1 2)\n  (foo))"), pair("t.lisp:2:8", "       ^"));
+        assert_eq!(at("(+ 1\n  (foo 2)"), pair("t.lisp:1:1", "^ this list is never closed"));
+        assert_eq!(at("(1 . 2 3)"), pair("t.lisp:1:8", "       ^"));
+        assert_eq!(at("(+ 1 1x)"), pair("t.lisp:1:6", "     ^^"));
+        assert_eq!(at("(f \"ab"), pair("t.lisp:1:4", "   ^^^ string starts here"));
+        assert_eq!(at("'"), pair("t.lisp:1:2", " ^ expected an expression"));
+        // the innermost failing expression is reported
+        assert_eq!(at("(setq '(x) 1)\n(+ x (/ 1 0))"), pair("t.lisp:2:6", "     ^^^^^^^"));
+        // errors in a lambda body point into the body, even in tail position
+        assert_eq!(at("(setq '(f) (\\ '(x) '(car x)))\n(f {})"), pair("t.lisp:1:21", "                    ^^^^^^^ got empty list here"));
+        // lists built at runtime have no span, the caller is reported
+        assert_eq!(at("(eval (list 'car {}))"), pair("t.lisp:1:1", "^^^^^^^^^^^^^^^^^^^^^ got nil here"));
+        // tabs are kept so the underline lines up
+        assert_eq!(at("\n\t(foo"), pair("t.lisp:2:2", "\t^ this list is never closed"));
+    }
+
+    #[test]
+    fn diagnostics_color_works() {
+        let d = Diagnostic::new("E0202", "division by zero".to_owned())
+            .primary(Some(Span::new(0, 1, 1, 1)), "");
+        assert_eq!(
+            d.render("-e", "x", true),
+            "\x1b[1;31merror[E0202]\x1b[0m\x1b[1m: division by zero\x1b[0m
+ \x1b[1;34m-->\x1b[0m -e:1:1
+\x1b[1;34m  |\x1b[0m
+\x1b[1;34m1 |\x1b[0m x
+\x1b[1;34m  |\x1b[0m \x1b[1;31m^\x1b[0m"
+        );
+    }
+}
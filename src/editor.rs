@@ -0,0 +1,1022 @@
+// Line editor for the REPL.
+//
+// On a terminal the line is edited in raw mode with emacs keys:
+//
+//   C-a C-e C-b C-f M-b M-f    move (Home, End and arrows work too)
+//   C-d C-h C-k C-u C-w M-d    delete, the last four save the text for C-y
+//   C-t                        swap the chars around the cursor
+//   C-p C-n                    walk the history (Up, Down)
+//   C-r                        search the history backwards, C-g cancels
+//   C-l                        clear the screen
+//   C-c                        drop the line, C-d on an empty line is EOF
+//   Tab                        complete the symbol before the cursor
+//
+// A Highlighter, if there is one, colors the line as it is typed.
+// When stdin isn't a terminal lines are read as they are.
+use std::env;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, Read, Write};
+use std::path::PathBuf;
+
+// Where the REPL gets its lines from
+pub trait LineReader {
+    // Shows `prompt` and reads one line, without the newline. None on EOF,
+    // an Interrupted error when the user drops the line with Ctrl-C.
+    fn read_line(&mut self, prompt: &str) -> io::Result<Option<String>>;
+}
+
+// A completion for the word before the cursor, functions with a
+// parameter list have it shown next to the name
+pub struct Candidate {
+    pub name: String,
+    pub params: Option<String>,
+}
+
+impl fmt::Display for Candidate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.params {
+            Some(v) => write!(f, "{} {}", self.name, v),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+pub trait Completer {
+    // Everything starting with `prefix`
+    fn complete(&self, prefix: &str) -> Vec<Candidate>;
+}
+
+// What a char of the line is, the editor picks the color for it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Highlight {
+    Plain,
+    Number,
+    Symbol,
+    Builtin,
+    Str,
+    Comment,
+    // The paren under the cursor and the one it pairs with
+    Match,
+    Error,
+}
+
+impl Highlight {
+    fn code(self) -> &'static str {
+        match self {
+            Highlight::Plain => "",
+            Highlight::Number => "\x1b[35m",
+            Highlight::Symbol => "\x1b[33m",
+            Highlight::Builtin => "\x1b[1;34m",
+            Highlight::Str => "\x1b[32m",
+            Highlight::Comment => "\x1b[90m",
+            Highlight::Match => "\x1b[1;4m",
+            Highlight::Error => "\x1b[1;31m",
+        }
+    }
+}
+
+pub trait Highlighter {
+    // One Highlight per char of `line`. `cursor` is None once the line is
+    // accepted.
+    fn highlight(&self, line: &[char], cursor: Option<usize>) -> Vec<Highlight>;
+}
+
+// No editing, the prompt is written to `out` and a line read from `input`
+pub struct Plain<R, W> {
+    input: R,
+    out: W,
+}
+
+impl<R: BufRead, W: Write> Plain<R, W> {
+    pub fn new(input: R, out: W) -> Plain<R, W> {
+        Plain { input, out }
+    }
+}
+
+impl<R: BufRead, W: Write> LineReader for Plain<R, W> {
+    fn read_line(&mut self, prompt: &str) -> io::Result<Option<String>> {
+        plain_read_line(&mut self.input, &mut self.out, prompt)
+    }
+}
+
+fn plain_read_line(input: &mut impl BufRead, out: &mut impl Write, prompt: &str) -> io::Result<Option<String>> {
+    write!(out, "{}", prompt)?;
+    out.flush()?;
+    let mut ret = String::new();
+    if input.read_line(&mut ret)? == 0 {
+        return Ok(None);
+    }
+    if ret.ends_with('\n') {
+        ret.pop();
+        if ret.ends_with('\r') {
+            ret.pop();
+        }
+    }
+    Ok(Some(ret))
+}
+
+// Past lines, oldest first. Each accepted line is appended to the file
+// right away so that history survives a crash.
+pub struct History {
+    entries: Vec<String>,
+    path: Option<PathBuf>,
+}
+
+const HISTORY_SIZE: usize = 1000;
+
+impl History {
+    pub fn new() -> History {
+        History {
+            entries: Vec::new(),
+            path: None,
+        }
+    }
+
+    // A missing or unreadable file gives an empty history. Files that grew
+    // past HISTORY_SIZE are cut down to the latest entries.
+    pub fn load(path: PathBuf) -> History {
+        let mut entries: Vec<String> = fs::read_to_string(&path)
+            .unwrap_or_default()
+            .lines()
+            .map(|v| v.to_owned())
+            .collect();
+        if entries.len() > HISTORY_SIZE {
+            entries.drain(..entries.len() - HISTORY_SIZE);
+            let _ = fs::write(&path, entries.join("\n") + "\n");
+        }
+        History {
+            entries,
+            path: Some(path),
+        }
+    }
+
+    // ~/.lis2_history
+    pub fn default_path() -> Option<PathBuf> {
+        env::var_os("HOME").map(|v| PathBuf::from(v).join(".lis2_history"))
+    }
+
+    // Blank lines and repeats of the last line aren't kept
+    pub fn add(&mut self, line: &str) {
+        if line.trim().is_empty() || self.entries.last().map(String::as_str) == Some(line) {
+            return;
+        }
+        self.entries.push(line.to_owned());
+        if let Some(path) = &self.path {
+            // Losing history isn't worth interrupting the session for
+            let file = OpenOptions::new().create(true).append(true).open(path);
+            let _ = file.and_then(|mut f| writeln!(f, "{}", line));
+        }
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Key {
+    Char(char),
+    // C-a is Ctrl('a')
+    Ctrl(char),
+    // M-b, sent as ESC b
+    Alt(char),
+    Enter,
+    Tab,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    WordLeft,
+    WordRight,
+    Esc,
+    Unknown,
+}
+
+fn read_byte(input: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut b = [0u8];
+    loop {
+        return match input.read(&mut b) {
+            Ok(0) => Ok(None),
+            Ok(_) => Ok(Some(b[0])),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => Err(e),
+        };
+    }
+}
+
+// Decodes one key press: a UTF-8 char, a control char or an escape
+// sequence. None at the end of input.
+fn read_key(input: &mut impl Read) -> io::Result<Option<Key>> {
+    let b = match read_byte(input)? {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    let key = match b {
+        b'\r' | b'\n' => Key::Enter,
+        b'\t' => Key::Tab,
+        0x7f | 0x08 => Key::Backspace,
+        0x1b => read_escape(input)?,
+        1..=26 => Key::Ctrl((b'a' + b - 1) as char),
+        0..=31 => Key::Unknown,
+        _ => {
+            let len = match b {
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                0xf0..=0xf7 => 4,
+                _ => 1,
+            };
+            let mut bytes = vec![b];
+            for _ in 1..len {
+                bytes.extend(read_byte(input)?);
+            }
+            match std::str::from_utf8(&bytes).ok().and_then(|v| v.chars().next()) {
+                Some(c) => Key::Char(c),
+                None => Key::Unknown,
+            }
+        }
+    };
+    Ok(Some(key))
+}
+
+// ESC [ A, ESC [ 3 ~, ESC [ 1 ; 5 C, ESC O H or ESC followed by a char
+fn read_escape(input: &mut impl Read) -> io::Result<Key> {
+    let b = match read_byte(input)? {
+        Some(v) => v,
+        None => return Ok(Key::Esc),
+    };
+    if b != b'[' && b != b'O' {
+        return Ok(match b {
+            0x1b => Key::Esc,
+            0x7f => Key::Alt('\x7f'),
+            b => Key::Alt((b as char).to_ascii_lowercase()),
+        });
+    }
+    let mut params = String::new();
+    let last = loop {
+        match read_byte(input)? {
+            Some(v @ b'0'..=b'9') | Some(v @ b';') => params.push(v as char),
+            Some(v) => break v,
+            None => return Ok(Key::Unknown),
+        }
+    };
+    // Modifier 5 is Ctrl, 3 is Alt
+    let word = params.ends_with(";5") || params.ends_with(";3");
+    Ok(match (last, params.as_str()) {
+        (b'A', _) => Key::Up,
+        (b'B', _) => Key::Down,
+        (b'C', _) if word => Key::WordRight,
+        (b'D', _) if word => Key::WordLeft,
+        (b'C', _) => Key::Right,
+        (b'D', _) => Key::Left,
+        (b'H', _) | (b'~', "1") | (b'~', "7") => Key::Home,
+        (b'F', _) | (b'~', "4") | (b'~', "8") => Key::End,
+        (b'~', "3") => Key::Delete,
+        _ => Key::Unknown,
+    })
+}
+
+// Symbols, numbers and words in strings; parens, quotes and blanks
+// separate them
+fn is_word(c: char) -> bool {
+    !c.is_whitespace() && !matches!(c, '(' | ')' | '{' | '}' | '\'' | '"')
+}
+
+struct Search {
+    query: String,
+    // History entry the match is in
+    found: Option<usize>,
+    // Line and history entry to go back to on C-g
+    orig: (Vec<char>, usize),
+}
+
+// A line being edited
+#[derive(Default)]
+struct State {
+    buf: Vec<char>,
+    pos: usize,
+    // History entry shown, entries.len() is the line being typed
+    index: usize,
+    // The line being typed while browsing the history
+    saved: Vec<char>,
+    search: Option<Search>,
+    // Accepted, drawn for the last time
+    done: bool,
+}
+
+enum Action {
+    Continue,
+    Accept,
+    Eof,
+    Interrupt,
+    ClearScreen,
+    // Lines to print below the one being edited
+    Show(Vec<String>),
+}
+
+impl State {
+    fn set(&mut self, v: &str) {
+        self.buf = v.chars().collect();
+        self.pos = self.buf.len();
+    }
+
+    fn word_left(&self) -> usize {
+        let mut i = self.pos;
+        while i > 0 && !is_word(self.buf[i - 1]) {
+            i -= 1;
+        }
+        while i > 0 && is_word(self.buf[i - 1]) {
+            i -= 1;
+        }
+        i
+    }
+
+    fn word_right(&self) -> usize {
+        let mut i = self.pos;
+        while i < self.buf.len() && !is_word(self.buf[i]) {
+            i += 1;
+        }
+        while i < self.buf.len() && is_word(self.buf[i]) {
+            i += 1;
+        }
+        i
+    }
+
+    // Removes buf[from..to] and puts the cursor where it was
+    fn cut(&mut self, from: usize, to: usize) -> String {
+        self.pos = from;
+        self.buf.drain(from..to).collect()
+    }
+}
+
+pub struct Editor {
+    history: History,
+    // Text of the last kill, for C-y
+    yank: String,
+    completer: Option<Box<dyn Completer>>,
+    highlighter: Option<Box<dyn Highlighter>>,
+}
+
+impl Editor {
+    pub fn new(history: History) -> Editor {
+        Editor {
+            history,
+            yank: String::new(),
+            completer: None,
+            highlighter: None,
+        }
+    }
+
+    pub fn completer(mut self, completer: Box<dyn Completer>) -> Editor {
+        self.completer = Some(completer);
+        self
+    }
+
+    pub fn highlighter(mut self, highlighter: Box<dyn Highlighter>) -> Editor {
+        self.highlighter = Some(highlighter);
+        self
+    }
+
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    // Edits one line reading keys from `input` and drawing on `out`, which
+    // is `cols` chars wide
+    fn edit(&mut self, prompt: &str, input: &mut impl Read, out: &mut impl Write, cols: usize) -> io::Result<Option<String>> {
+        let mut st = State {
+            index: self.history.entries.len(),
+            ..State::default()
+        };
+        write!(out, "{}", self.refresh(prompt, &st, cols))?;
+        out.flush()?;
+        while let Some(key) = read_key(input)? {
+            match self.handle(&mut st, key) {
+                Action::Continue => (),
+                Action::ClearScreen => write!(out, "\x1b[H\x1b[2J")?,
+                Action::Show(items) => write!(out, "{}", columns(&items, cols))?,
+                Action::Accept => {
+                    st.search = None;
+                    st.done = true;
+                    write!(out, "{}\r\n", self.refresh(prompt, &st, cols))?;
+                    let line: String = st.buf.iter().collect();
+                    self.history.add(&line);
+                    return Ok(Some(line));
+                }
+                Action::Eof => return Ok(None),
+                Action::Interrupt => {
+                    write!(out, "^C\r\n")?;
+                    return Err(io::Error::new(io::ErrorKind::Interrupted, "line dropped"));
+                }
+            }
+            write!(out, "{}", self.refresh(prompt, &st, cols))?;
+            out.flush()?;
+        }
+        Ok(None)
+    }
+
+    fn handle(&mut self, st: &mut State, key: Key) -> Action {
+        if st.search.is_some() {
+            match self.handle_search(st, key) {
+                Some(action) => return action,
+                // Any other key ends the search and works as usual
+                None => st.search = None,
+            }
+        }
+        let len = st.buf.len();
+        match key {
+            Key::Char(c) => {
+                st.buf.insert(st.pos, c);
+                st.pos += 1;
+            }
+            Key::Enter => return Action::Accept,
+            Key::Ctrl('c') => return Action::Interrupt,
+            Key::Ctrl('d') if len == 0 => return Action::Eof,
+            Key::Ctrl('l') => return Action::ClearScreen,
+            Key::Ctrl('a') | Key::Home => st.pos = 0,
+            Key::Ctrl('e') | Key::End => st.pos = len,
+            Key::Ctrl('b') | Key::Left => st.pos = st.pos.saturating_sub(1),
+            Key::Ctrl('f') | Key::Right => st.pos = (st.pos + 1).min(len),
+            Key::Alt('b') | Key::WordLeft => st.pos = st.word_left(),
+            Key::Alt('f') | Key::WordRight => st.pos = st.word_right(),
+            Key::Backspace | Key::Ctrl('h') if st.pos > 0 => {
+                st.pos -= 1;
+                st.buf.remove(st.pos);
+            }
+            Key::Delete | Key::Ctrl('d') if st.pos < len => {
+                st.buf.remove(st.pos);
+            }
+            Key::Ctrl('k') => self.yank = st.cut(st.pos, len),
+            Key::Ctrl('u') => self.yank = st.cut(0, st.pos),
+            Key::Ctrl('w') | Key::Alt('\x7f') => self.yank = st.cut(st.word_left(), st.pos),
+            Key::Alt('d') => {
+                let (from, to) = (st.pos, st.word_right());
+                self.yank = st.cut(from, to);
+            }
+            Key::Ctrl('y') => {
+                for c in self.yank.chars() {
+                    st.buf.insert(st.pos, c);
+                    st.pos += 1;
+                }
+            }
+            // At the end of the line the last two chars are swapped
+            Key::Ctrl('t') if len >= 2 && st.pos > 0 => {
+                let i = st.pos.min(len - 1);
+                st.buf.swap(i - 1, i);
+                st.pos = i + 1;
+            }
+            Key::Ctrl('p') | Key::Up if st.index > 0 => self.show_entry(st, st.index - 1),
+            Key::Ctrl('n') | Key::Down if st.index < self.history.entries.len() => {
+                self.show_entry(st, st.index + 1)
+            }
+            Key::Tab => return self.complete(st),
+            Key::Ctrl('r') => {
+                st.search = Some(Search {
+                    query: String::new(),
+                    found: None,
+                    orig: (st.buf.clone(), st.index),
+                })
+            }
+            _ => (),
+        }
+        Action::Continue
+    }
+
+    // Inserts what all candidates for the word before the cursor have in
+    // common. A unique match gets a space after it, its parameter list is
+    // shown. When nothing can be inserted all candidates are listed.
+    fn complete(&self, st: &mut State) -> Action {
+        let completer = match &self.completer {
+            Some(v) => v,
+            None => return Action::Continue,
+        };
+        let mut start = st.pos;
+        while start > 0 && is_word(st.buf[start - 1]) {
+            start -= 1;
+        }
+        if start == st.pos {
+            return Action::Continue;
+        }
+        let prefix: String = st.buf[start..st.pos].iter().collect();
+        let found = completer.complete(&prefix);
+        let common = match found.first() {
+            Some(v) => found.iter().fold(v.name.as_str(), |acc, v| {
+                let len = acc
+                    .char_indices()
+                    .zip(v.name.chars())
+                    .find(|((_, a), b)| a != b)
+                    .map_or(acc.len().min(v.name.len()), |((i, _), _)| i);
+                &acc[..len]
+            }),
+            None => return Action::Continue,
+        };
+        let rest: Vec<char> = common.chars().skip(prefix.chars().count()).collect();
+        let inserted = !rest.is_empty();
+        for c in rest {
+            st.buf.insert(st.pos, c);
+            st.pos += 1;
+        }
+        match found.as_slice() {
+            [v] => {
+                if st.pos == st.buf.len() {
+                    st.buf.push(' ');
+                    st.pos += 1;
+                }
+                match v.params {
+                    Some(_) => Action::Show(vec![v.to_string()]),
+                    None => Action::Continue,
+                }
+            }
+            _ if inserted => Action::Continue,
+            _ => Action::Show(found.iter().map(|v| v.to_string()).collect()),
+        }
+    }
+
+    fn show_entry(&self, st: &mut State, index: usize) {
+        if st.index == self.history.entries.len() {
+            st.saved = st.buf.clone();
+        }
+        st.index = index;
+        match self.history.entries.get(index) {
+            Some(v) => st.set(v),
+            None => {
+                st.buf = st.saved.clone();
+                st.pos = st.buf.len();
+            }
+        }
+    }
+
+    // Newest entry before `before` containing `query`, and where
+    fn find(&self, query: &str, before: usize) -> Option<(usize, usize)> {
+        self.history.entries[..before]
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(i, v)| Some((i, v.find(query)?)))
+    }
+
+    // None for keys that end the search and should be handled as usual
+    fn handle_search(&mut self, st: &mut State, key: Key) -> Option<Action> {
+        let search = st.search.as_mut()?;
+        let before = match key {
+            Key::Char(c) => {
+                search.query.push(c);
+                // The current match may still do
+                search.found.map_or(self.history.entries.len(), |v| v + 1)
+            }
+            Key::Backspace | Key::Ctrl('h') => {
+                search.query.pop();
+                self.history.entries.len()
+            }
+            Key::Ctrl('r') => search.found.unwrap_or(self.history.entries.len()),
+            Key::Ctrl('g') | Key::Esc => {
+                let (buf, index) = st.search.take()?.orig;
+                st.buf = buf;
+                st.pos = st.buf.len();
+                st.index = index;
+                return Some(Action::Continue);
+            }
+            Key::Ctrl('c') => return Some(Action::Interrupt),
+            Key::Enter => return Some(Action::Accept),
+            _ => return None,
+        };
+        if st.index == self.history.entries.len() {
+            st.saved = search.orig.0.clone();
+        }
+        if search.query.is_empty() {
+            return Some(Action::Continue);
+        }
+        if let Some((i, at)) = self.find(&search.query, before) {
+            search.found = Some(i);
+            st.index = i;
+            st.set(&self.history.entries[i]);
+            st.pos = self.history.entries[i][..at].chars().count();
+        }
+        Some(Action::Continue)
+    }
+
+    // Redraws the line in place. Lines wider than the terminal scroll so
+    // that the cursor stays visible.
+    fn refresh(&self, prompt: &str, st: &State, cols: usize) -> String {
+        let prompt = match &st.search {
+            Some(s) => {
+                let failed = !s.query.is_empty() && self.find(&s.query, self.history.entries.len()).is_none();
+                format!("({}reverse-i-search)'{}': ", if failed { "failed " } else { "" }, s.query)
+            }
+            None => prompt.to_owned(),
+        };
+        let width = prompt.chars().count();
+        let avail = cols.saturating_sub(width + 1).max(1);
+        let start = st.pos.saturating_sub(avail);
+        let end = (start + avail).min(st.buf.len());
+        let visible = match &self.highlighter {
+            Some(h) => {
+                let cursor = if st.done { None } else { Some(st.pos) };
+                paint(&st.buf[start..end], &h.highlight(&st.buf, cursor)[start..end])
+            }
+            None => st.buf[start..end].iter().collect(),
+        };
+        let mut ret = format!("\r{}{}\x1b[K\r", prompt, visible);
+        let col = width + st.pos - start;
+        if col > 0 {
+            ret += &format!("\x1b[{}C", col);
+        }
+        ret
+    }
+}
+
+// `chars` with escape codes around each run of the same highlight
+fn paint(chars: &[char], highlights: &[Highlight]) -> String {
+    let mut ret = String::new();
+    let mut last = Highlight::Plain;
+    for (c, h) in chars.iter().zip(highlights) {
+        if *h != last {
+            if last != Highlight::Plain {
+                ret += "\x1b[0m";
+            }
+            ret += h.code();
+            last = *h;
+        }
+        ret.push(*c);
+    }
+    if last != Highlight::Plain {
+        ret += "\x1b[0m";
+    }
+    ret
+}
+
+// Completion candidates laid out in columns under the line being edited
+fn columns(items: &[String], cols: usize) -> String {
+    let width = items.iter().map(|v| v.chars().count()).max().unwrap_or(0) + 2;
+    let mut ret = String::new();
+    for row in items.chunks((cols / width).max(1)) {
+        let line: String = row.iter().map(|v| format!("{:<w$}", v, w = width)).collect();
+        ret += &format!("\r\n{}", line.trim_end());
+    }
+    ret + "\r\n"
+}
+
+impl LineReader for Editor {
+    fn read_line(&mut self, prompt: &str) -> io::Result<Option<String>> {
+        let stdin = io::stdin();
+        let mut input = stdin.lock();
+        let mut out = io::stdout();
+        let dumb = env::var("TERM").map_or(true, |v| v == "dumb");
+        let raw = match sys::is_tty() && !dumb {
+            true => sys::RawMode::enable().ok(),
+            false => None,
+        };
+        match raw {
+            Some(_raw) => self.edit(prompt, &mut input, &mut out, sys::columns().unwrap_or(80)),
+            None => plain_read_line(&mut input, &mut out, prompt),
+        }
+    }
+}
+
+// The termios layout, flags and ioctl numbers below are those of Linux on
+// x86, x86_64 and aarch64, some other architectures differ in places
+#[cfg(all(target_os = "linux", any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+mod sys {
+    use std::io;
+    use std::os::raw::{c_int, c_uchar, c_uint, c_ulong, c_ushort};
+
+    // struct termios from <termios.h> on Linux
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct Termios {
+        c_iflag: c_uint,
+        c_oflag: c_uint,
+        c_cflag: c_uint,
+        c_lflag: c_uint,
+        c_line: c_uchar,
+        c_cc: [c_uchar; 32],
+        c_ispeed: c_uint,
+        c_ospeed: c_uint,
+    }
+
+    #[repr(C)]
+    struct Winsize {
+        ws_row: c_ushort,
+        ws_col: c_ushort,
+        ws_xpixel: c_ushort,
+        ws_ypixel: c_ushort,
+    }
+
+    extern "C" {
+        fn isatty(fd: c_int) -> c_int;
+        fn tcgetattr(fd: c_int, termios: *mut Termios) -> c_int;
+        fn tcsetattr(fd: c_int, action: c_int, termios: *const Termios) -> c_int;
+        fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+    }
+
+    const STDIN: c_int = 0;
+    const STDOUT: c_int = 1;
+    // Waits for pending output but keeps pending input, so text pasted
+    // while switching modes isn't lost
+    const TCSADRAIN: c_int = 1;
+    const TIOCGWINSZ: c_ulong = 0x5413;
+    const VTIME: usize = 5;
+    const VMIN: usize = 6;
+    // c_iflag
+    const BRKINT: c_uint = 0o2;
+    const INPCK: c_uint = 0o20;
+    const ISTRIP: c_uint = 0o40;
+    const ICRNL: c_uint = 0o400;
+    const IXON: c_uint = 0o2000;
+    // c_oflag
+    const OPOST: c_uint = 0o1;
+    // c_cflag
+    const CS8: c_uint = 0o60;
+    // c_lflag
+    const ISIG: c_uint = 0o1;
+    const ICANON: c_uint = 0o2;
+    const ECHO: c_uint = 0o10;
+    const IEXTEN: c_uint = 0o100000;
+
+    pub fn is_tty() -> bool {
+        unsafe { isatty(STDIN) == 1 && isatty(STDOUT) == 1 }
+    }
+
+    pub fn columns() -> Option<usize> {
+        let mut ws = Winsize {
+            ws_row: 0,
+            ws_col: 0,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        match unsafe { ioctl(STDOUT, TIOCGWINSZ, &mut ws as *mut Winsize) } {
+            0 if ws.ws_col > 0 => Some(ws.ws_col as usize),
+            _ => None,
+        }
+    }
+
+    // Terminal in raw mode for as long as this lives
+    pub struct RawMode {
+        orig: Termios,
+    }
+
+    impl RawMode {
+        pub fn enable() -> io::Result<RawMode> {
+            let mut orig = Termios {
+                c_iflag: 0,
+                c_oflag: 0,
+                c_cflag: 0,
+                c_lflag: 0,
+                c_line: 0,
+                c_cc: [0; 32],
+                c_ispeed: 0,
+                c_ospeed: 0,
+            };
+            if unsafe { tcgetattr(STDIN, &mut orig) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut raw = orig;
+            raw.c_iflag &= !(BRKINT | ICRNL | INPCK | ISTRIP | IXON);
+            raw.c_oflag &= !OPOST;
+            raw.c_cflag |= CS8;
+            raw.c_lflag &= !(ECHO | ICANON | IEXTEN | ISIG);
+            raw.c_cc[VMIN] = 1;
+            raw.c_cc[VTIME] = 0;
+            if unsafe { tcsetattr(STDIN, TCSADRAIN, &raw) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(RawMode { orig })
+        }
+    }
+
+    impl Drop for RawMode {
+        fn drop(&mut self) {
+            unsafe {
+                tcsetattr(STDIN, TCSADRAIN, &self.orig);
+            }
+        }
+    }
+}
+
+// No raw mode elsewhere, lines are always read as they are
+#[cfg(not(all(target_os = "linux", any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))))]
+mod sys {
+    use std::io;
+
+    pub fn is_tty() -> bool {
+        false
+    }
+
+    pub fn columns() -> Option<usize> {
+        None
+    }
+
+    pub struct RawMode;
+
+    impl RawMode {
+        pub fn enable() -> io::Result<RawMode> {
+            Err(io::Error::other("raw mode is only supported on x86, x86_64 and aarch64 Linux"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(entries: &[&str]) -> History {
+        History {
+            entries: entries.iter().map(|v| v.to_string()).collect(),
+            path: None,
+        }
+    }
+
+    // Line accepted after typing `keys`
+    fn type_keys(editor: &mut Editor, keys: &str) -> io::Result<Option<String>> {
+        editor.edit("> ", &mut keys.as_bytes(), &mut Vec::new(), 80)
+    }
+
+    #[test]
+    fn editor_keys_works() {
+        let mut e = Editor::new(History::new());
+        let mut line = |keys: &str| type_keys(&mut e, keys).unwrap().unwrap();
+        assert_eq!(line("(+ 1 2)\r"), "(+ 1 2)");
+        assert_eq!(line("bc\x01a\x05d\r"), "abcd");
+        assert_eq!(line("ac\x1b[Db\x1b[C\x1b[Cd\n"), "abcd");
+        assert_eq!(line("abc\x7f\x08d\r"), "ad");
+        assert_eq!(line("abc\x01\x04\x1b[3~\r"), "c");
+        assert_eq!(line("(foo bar-baz)\x1bb\x1bbX\x1bfY\r"), "(XfooY bar-baz)");
+        assert_eq!(line("(foo bar)\x1b[1;5D\x1b[1;5DX\r"), "(Xfoo bar)");
+        assert_eq!(line("a b c\x02\x02\x0b\x19\x19\r"), "a b c c");
+        assert_eq!(line("a b c\x02\x02\x15\x05\x19\r"), " ca b");
+        assert_eq!(line("(car lst)\x02\x17x\r"), "(car x)");
+        assert_eq!(line("(car lst)\x01\x1bf\x1bd\r"), "(car)");
+        assert_eq!(line("ab\x14\r"), "ba");
+        assert_eq!(line("abc\x01\x06\x14\r"), "bac");
+        assert_eq!(line("λé😀\x02\x7f\r"), "λ😀");
+        assert_eq!(type_keys(&mut e, "\x04").unwrap(), None);
+        assert_eq!(type_keys(&mut e, "").unwrap(), None);
+        assert_eq!(
+            type_keys(&mut e, "abc\x03").unwrap_err().kind(),
+            io::ErrorKind::Interrupted
+        );
+    }
+
+    #[test]
+    fn editor_history_works() {
+        let mut e = Editor::new(history(&["one", "two"]));
+        let mut line = |keys: &str| type_keys(&mut e, keys).unwrap().unwrap();
+        assert_eq!(line("\x1b[A\r"), "two");
+        assert_eq!(line("\x10\x10\x10\r"), "one");
+        // the typed line comes back after walking past the newest entry
+        assert_eq!(line("new\x10\x10\x0e\x0e\r"), "new");
+        assert_eq!(line("\x1b[A!\r"), "new!");
+        assert_eq!(line("  \r"), "  ");
+        assert_eq!(e.history().entries(), ["one", "two", "one", "new", "new!"]);
+
+        let line = |keys: &str| {
+            let mut e = Editor::new(history(&["(car x)", "(cdr y)", "(cons x y)", "(+ 1 2)"]));
+            type_keys(&mut e, keys).unwrap().unwrap()
+        };
+        assert_eq!(line("\x12x\r"), "(cons x y)");
+        assert_eq!(line("\x12x\x12\r"), "(car x)");
+        assert_eq!(line("\x12x\x12\x12\r"), "(car x)");
+        assert_eq!(line("\x12cd\x7f\x7fc\x12\r"), "(cdr y)");
+        // other keys leave the search with the match
+        assert_eq!(line("\x12cdr\x05!\r"), "(cdr y)!");
+        assert_eq!(line("typed\x12car\x07\r"), "typed");
+        assert_eq!(line("typed\x12\x07\r"), "typed");
+        assert_eq!(line("\x1b[A\x12car\x07\r"), "(+ 1 2)");
+        assert_eq!(line("\x12z\r"), "");
+
+        let mut e = Editor::new(History::new());
+        let mut out = Vec::new();
+        e.edit("> ", &mut "\x12q\r".as_bytes(), &mut out, 80).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("(failed reverse-i-search)'q': "), "{:?}", out);
+    }
+
+    struct Names(&'static [(&'static str, Option<&'static str>)]);
+
+    impl Completer for Names {
+        fn complete(&self, prefix: &str) -> Vec<Candidate> {
+            self.0
+                .iter()
+                .filter(|(name, _)| name.starts_with(prefix))
+                .map(|(name, params)| Candidate {
+                    name: name.to_string(),
+                    params: params.map(|v| v.to_owned()),
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn editor_completion_works() {
+        let names = &[("car", None), ("cdr", None), ("concat", None), ("cons", None), ("λf", Some("(x y)"))];
+        let mut e = Editor::new(History::new()).completer(Box::new(Names(names)));
+        let mut line = |keys: &str| {
+            let mut out = Vec::new();
+            let line = e.edit("> ", &mut keys.as_bytes(), &mut out, 20).unwrap().unwrap();
+            (line, String::from_utf8(out).unwrap())
+        };
+        assert_eq!(line("(ca\t\r").0, "(car ");
+        assert_eq!(line("(conc\t1)\r").0, "(concat 1)");
+        // the common part first, the candidates on the next tab
+        assert_eq!(line("(co\t\r").0, "(con");
+        let (text, out) = line("(co\t\t\r");
+        assert_eq!(text, "(con");
+        assert!(out.contains("\r\nconcat  cons\r\n"), "{:?}", out);
+        let (_, out) = line("(c\t\r");
+        assert!(out.contains("\r\ncar     cdr\r\nconcat  cons\r\n"), "{:?}", out);
+        // no space is added in the middle of a line
+        assert_eq!(line("(ca x)\x02\x02\x02\t\r").0, "(car x)");
+        let (text, out) = line("'(λ\t\r");
+        assert_eq!(text, "'(λf ");
+        assert!(out.contains("\r\nλf (x y)\r\n"), "{:?}", out);
+        assert_eq!(line("(x\t\r").0, "(x");
+        assert_eq!(line("(\t\r").0, "(");
+    }
+
+    #[test]
+    fn editor_refresh_works() {
+        let e = Editor::new(History::new());
+        let mut st = State::default();
+        st.set("(+ 1 2)");
+        assert_eq!(e.refresh("> ", &st, 80), "\r> (+ 1 2)\x1b[K\r\x1b[9C");
+        st.pos = 0;
+        assert_eq!(e.refresh("> ", &st, 80), "\r> (+ 1 2)\x1b[K\r\x1b[2C");
+        // scrolls to keep the cursor in view
+        st.set("abcdefghij");
+        assert_eq!(e.refresh("> ", &st, 8), "\r> fghij\x1b[K\r\x1b[7C");
+        assert_eq!(e.refresh("", &State::default(), 8), "\r\x1b[K\r");
+    }
+
+    // Digits are numbers, the char under the cursor is a match
+    struct Digits;
+
+    impl Highlighter for Digits {
+        fn highlight(&self, line: &[char], cursor: Option<usize>) -> Vec<Highlight> {
+            let kind = |(i, c): (usize, &char)| match c {
+                _ if Some(i) == cursor => Highlight::Match,
+                '0'..='9' => Highlight::Number,
+                _ => Highlight::Plain,
+            };
+            line.iter().enumerate().map(kind).collect()
+        }
+    }
+
+    #[test]
+    fn editor_highlight_works() {
+        let e = Editor::new(History::new()).highlighter(Box::new(Digits));
+        let mut st = State::default();
+        st.set("(+ 12 x)");
+        st.pos = 3;
+        assert_eq!(
+            e.refresh("> ", &st, 80),
+            "\r> (+ \x1b[1;4m1\x1b[0m\x1b[35m2\x1b[0m x)\x1b[K\r\x1b[5C"
+        );
+        // codes are only emitted for the visible part
+        st.pos = 5;
+        assert_eq!(e.refresh("> ", &st, 6), "\r>  \x1b[35m12\x1b[0m\x1b[K\r\x1b[5C");
+        st.done = true;
+        st.pos = st.buf.len();
+        assert_eq!(e.refresh("> ", &st, 80), "\r> (+ \x1b[35m12\x1b[0m x)\x1b[K\r\x1b[10C");
+    }
+
+    #[test]
+    fn history_file_works() {
+        let path = env::temp_dir().join(format!("lis2_history_test_{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut h = History::load(path.clone());
+        assert!(h.entries().is_empty());
+        for v in ["(+ 1 2)", "(+ 1 2)", "", "x"] {
+            h.add(v);
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), "(+ 1 2)\nx\n");
+        assert_eq!(History::load(path.clone()).entries(), ["(+ 1 2)", "x"]);
+
+        let lines: Vec<String> = (0..HISTORY_SIZE + 5).map(|v| v.to_string()).collect();
+        fs::write(&path, lines.join("\n")).unwrap();
+        let h = History::load(path.clone());
+        assert_eq!(h.entries(), &lines[5..]);
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), HISTORY_SIZE);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn plain_works() {
+        let mut out = Vec::new();
+        let mut p = Plain::new("a\r\nb\nc".as_bytes(), &mut out);
+        assert_eq!(p.read_line("> ").unwrap(), Some("a".to_owned()));
+        assert_eq!(p.read_line("> ").unwrap(), Some("b".to_owned()));
+        assert_eq!(p.read_line("> ").unwrap(), Some("c".to_owned()));
+        assert_eq!(p.read_line("> ").unwrap(), None);
+        assert_eq!(out, b"> > > > ");
+    }
+}
@@ -1,6 +1,7 @@
-use std::collections::HashMap;
-use crate::ast::{Val, FuncType};
+use std::collections::{BTreeMap, HashMap};
+use crate::ast::{Val, FuncType, Number};
 use crate::builtin;
+use std::cmp::Ordering;
 use std::rc::Rc;
 use std::sync::RwLock;
 
@@ -35,20 +36,71 @@ impl Clone for Env {
 
 // TODO: Implement Eq properly
 impl PartialEq for Env{
-    fn eq(&self, other: &Self) -> bool {
+    fn eq(&self, _other: &Self) -> bool {
         true
     }
 }
 
 impl  Env  {
 
-    fn register_builtins(&mut self) {
-        self.put("+".to_owned(), FuncType::new_closure(builtin::op(0, |a, b| { a + b }), "+"));
-        self.put("-".to_owned(), FuncType::new_closure(builtin::op(0, |a, b| { a - b }), "-"));
-        self.put("*".to_owned(), FuncType::new_closure(builtin::op(1, |a, b| { a * b }), "*"));
-        self.put("/".to_owned(), FuncType::new_closure(builtin::op(1, |a, b| { a / b }), "/"));
-        self.put("setq".to_owned(), FuncType::new_function(builtin::setq));
-        self.put("\\".to_owned(), FuncType::new_function(builtin::lambda));
+    fn register_builtins(&self) {
+        self.put("+".to_owned(), FuncType::new_closure(builtin::op("+", Number::new(0), Number::checked_add), "+"));
+        self.put("-".to_owned(), FuncType::new_closure(builtin::op("-", Number::new(0), Number::checked_sub), "-"));
+        self.put("*".to_owned(), FuncType::new_closure(builtin::op("*", Number::new(1), Number::checked_mul), "*"));
+        self.put("/".to_owned(), FuncType::new_closure(builtin::op("/", Number::new(1), Number::checked_div), "/"));
+        self.put("=".to_owned(), FuncType::new_closure(builtin::cmp("=", |o| o == Ordering::Equal), "="));
+        self.put("<".to_owned(), FuncType::new_closure(builtin::cmp("<", |o| o == Ordering::Less), "<"));
+        self.put(">".to_owned(), FuncType::new_closure(builtin::cmp(">", |o| o == Ordering::Greater), ">"));
+        self.put("<=".to_owned(), FuncType::new_closure(builtin::cmp("<=", |o| o != Ordering::Greater), "<="));
+        self.put(">=".to_owned(), FuncType::new_closure(builtin::cmp(">=", |o| o != Ordering::Less), ">="));
+        self.put("not".to_owned(), FuncType::new_function(builtin::not, "not"));
+        self.put("eq?".to_owned(), FuncType::new_function(builtin::is_eq, "eq?"));
+        self.put("equal?".to_owned(), FuncType::new_function(builtin::is_equal, "equal?"));
+        self.put("quotient".to_owned(), FuncType::new_function(builtin::quotient, "quotient"));
+        self.put("mod".to_owned(), FuncType::new_function(builtin::modulo, "mod"));
+        self.put("expt".to_owned(), FuncType::new_function(builtin::expt, "expt"));
+        self.put("gcd".to_owned(), FuncType::new_function(builtin::gcd, "gcd"));
+        self.put("setq".to_owned(), FuncType::new_function(builtin::setq, "setq"));
+        self.put("set!".to_owned(), FuncType::new_function(builtin::set, "set!"));
+        self.put("\\".to_owned(), FuncType::new_function(builtin::lambda, "\\"));
+        self.put("list".to_owned(), FuncType::new_function(builtin::list, "list"));
+        self.put("head".to_owned(), FuncType::new_function(builtin::head, "head"));
+        self.put("tail".to_owned(), FuncType::new_function(builtin::tail, "tail"));
+        self.put("init".to_owned(), FuncType::new_function(builtin::init, "init"));
+        self.put("join".to_owned(), FuncType::new_function(builtin::join, "join"));
+        self.put("cons".to_owned(), FuncType::new_function(builtin::cons, "cons"));
+        self.put("error".to_owned(), FuncType::new_function(builtin::error, "error"));
+        self.put("car".to_owned(), FuncType::new_function(builtin::car, "car"));
+        self.put("cdr".to_owned(), FuncType::new_function(builtin::cdr, "cdr"));
+        self.put("set-car!".to_owned(), FuncType::new_function(builtin::set_car, "set-car!"));
+        self.put("set-cdr!".to_owned(), FuncType::new_function(builtin::set_cdr, "set-cdr!"));
+        self.put("pair?".to_owned(), FuncType::new_function(builtin::is_pair, "pair?"));
+        self.put("assoc".to_owned(), FuncType::new_function(builtin::assoc, "assoc"));
+        self.put("len".to_owned(), FuncType::new_function(builtin::len, "len"));
+        self.put("nth".to_owned(), FuncType::new_function(builtin::nth, "nth"));
+        self.put("eval".to_owned(), FuncType::new_function(builtin::eval, "eval"));
+        self.put("partial".to_owned(), FuncType::new_function(builtin::partial, "partial"));
+        self.put("curry".to_owned(), FuncType::new_function(builtin::curry, "curry"));
+        self.put("if".to_owned(), FuncType::new_special(builtin::special_if, "if"));
+        self.put("cond".to_owned(), FuncType::new_special(builtin::special_cond, "cond"));
+        self.put("when".to_owned(), FuncType::new_special(builtin::special_when, "when"));
+        self.put("unless".to_owned(), FuncType::new_special(builtin::special_unless, "unless"));
+        self.put("and".to_owned(), FuncType::new_special(builtin::special_and, "and"));
+        self.put("or".to_owned(), FuncType::new_special(builtin::special_or, "or"));
+        self.put("begin".to_owned(), FuncType::new_special(builtin::special_begin, "begin"));
+        self.put("do".to_owned(), FuncType::new_special(builtin::special_begin, "do"));
+        self.put("concat".to_owned(), FuncType::new_function(builtin::concat, "concat"));
+        self.put("string-length".to_owned(), FuncType::new_function(builtin::string_length, "string-length"));
+        self.put("substring".to_owned(), FuncType::new_function(builtin::substring, "substring"));
+        self.put("index-of".to_owned(), FuncType::new_function(builtin::index_of, "index-of"));
+        self.put("split".to_owned(), FuncType::new_function(builtin::split, "split"));
+        self.put("string-join".to_owned(), FuncType::new_function(builtin::string_join, "string-join"));
+        self.put("upcase".to_owned(), FuncType::new_function(builtin::upcase, "upcase"));
+        self.put("downcase".to_owned(), FuncType::new_function(builtin::downcase, "downcase"));
+        self.put("string->symbol".to_owned(), FuncType::new_function(builtin::string_to_symbol, "string->symbol"));
+        self.put("number->string".to_owned(), FuncType::new_function(builtin::number_to_string, "number->string"));
+        self.put("display".to_owned(), FuncType::new_function(builtin::display, "display"));
+        self.put("write".to_owned(), FuncType::new_function(builtin::write, "write"));
 
 
     }
@@ -66,12 +118,16 @@ impl  Env  {
 
     }
 
+    // Only the root environment holds the builtins, child environments
+    // (call frames) see them through the parent chain
     pub fn new(par: ParentEnv) -> Env {
-        let mut ret = Env {
+        let ret = Env {
             env: RwLock::new(HashMap::new()),
-            par: par
+            par
         };
-        ret.register_builtins();
+        if ret.par.is_none() {
+            ret.register_builtins();
+        }
         ret
         
     }
@@ -88,10 +144,54 @@ impl  Env  {
         
 
     }
+    pub fn set(&self, k: &str, v: Val) -> Result<(), EnvError> {
+        let mut m = self.env.write().unwrap();
+
+        match m.get_mut(k) {
+            Some(old) => {
+                *old = v;
+                Ok(())
+            }
+            None => match &self.par {
+                Some(p) => p.set(k, v),
+                None => Err(EnvError {
+                    error: "Env variable is not bound"
+                }),
+            }
+        }
+    }
+
     pub fn put(&self, k: String, v: Val) {
         let mut m = self.env.write().unwrap();
         m.insert(k, v);
-        ()
+    }
+
+    // Drops every binding made here, the root environment gets its
+    // builtins back
+    pub fn reset(&self) {
+        self.env.write().unwrap().clear();
+        if self.par.is_none() {
+            self.register_builtins();
+        }
+    }
+
+    // Every binding visible from here, including the ones in parent
+    // environments, sorted by name. Inner bindings shadow outer ones.
+    pub fn bindings(&self) -> Vec<(String, Val)> {
+        let mut ret = BTreeMap::new();
+        let mut env = Some(self);
+        while let Some(e) = env {
+            for (k, v) in e.env.read().unwrap().iter() {
+                ret.entry(k.to_owned()).or_insert_with(|| Rc::clone(v));
+            }
+            env = e.par.as_deref();
+        }
+        ret.into_iter().collect()
+    }
+
+    // Names of `bindings`
+    pub fn keys(&self) -> Vec<String> {
+        self.bindings().into_iter().map(|(k, _)| k).collect()
     }
 
 }
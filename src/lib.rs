@@ -1,6 +1,14 @@
 pub mod parser;
 pub mod token;
+pub mod span;
+pub mod diagnostics;
+pub mod printer;
+pub mod editor;
 pub mod repl;
 pub mod ast;
+pub mod number;
+pub mod bigint;
 pub mod env;
 mod builtin;
+#[cfg(test)]
+mod testing;
@@ -0,0 +1,443 @@
+use crate::bigint::BigInt;
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+// Numeric tower: Int ⊂ Ratio ⊂ Float. Arithmetic on two numbers promotes
+// both to the wider of their types, and exact results are demoted back
+// (a Ratio with denominator 1 is always stored as an Int). Integers that
+// don't fit into i128 are transparently promoted to Big and demoted back
+// once they fit again.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Number {
+    Int(i128),
+    // Never holds a value that fits into i128
+    Big(BigInt),
+    // Always normalized: gcd(num, den) == 1 and den > 1
+    Ratio(i128, i128),
+    Float(f64),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct NumberParseError;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum NumberError {
+    DivisionByZero,
+    Overflow,
+}
+
+// Largest exact power expt computes, 2^15 limbs or about 315000 digits
+const MAX_POW_BITS: u64 = 1 << 20;
+
+fn gcd(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        let t = a % b;
+        a = b;
+        b = t;
+    }
+    a
+}
+
+impl Number {
+    pub fn new(val: i128) -> Number {
+        Number::Int(val)
+    }
+
+    pub fn big(val: BigInt) -> Number {
+        match val.to_i128() {
+            Some(v) => Number::Int(v),
+            None => Number::Big(val),
+        }
+    }
+
+    pub fn ratio(num: i128, den: i128) -> Result<Number, NumberError> {
+        Number::big_ratio(BigInt::from_i128(num), BigInt::from_i128(den))
+    }
+
+    // Ratio parts are limited to i128, anything larger is an overflow
+    fn big_ratio(num: BigInt, den: BigInt) -> Result<Number, NumberError> {
+        if den.is_zero() {
+            return Err(NumberError::DivisionByZero);
+        }
+        let g = num.gcd(&den);
+        let (mut num, _) = num.divrem(&g).unwrap();
+        let (mut den, _) = den.divrem(&g).unwrap();
+        if den.is_negative() {
+            num = num.neg();
+            den = den.neg();
+        }
+        match den.to_i128() {
+            Some(1) => Ok(Number::big(num)),
+            Some(d) => match num.to_i128() {
+                Some(n) => Ok(Number::Ratio(n, d)),
+                None => Err(NumberError::Overflow),
+            },
+            None => Err(NumberError::Overflow),
+        }
+    }
+
+    pub fn float(val: f64) -> Number {
+        Number::Float(val)
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Number::Int(v) => *v as f64,
+            Number::Big(v) => v.to_f64(),
+            Number::Ratio(n, d) => *n as f64 / *d as f64,
+            Number::Float(v) => *v,
+        }
+    }
+
+    // Which kind of number this is, for errors where "number" would be
+    // ambiguous
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Number::Int(_) | Number::Big(_) => "integer",
+            Number::Ratio(..) => "ratio",
+            Number::Float(_) => "float",
+        }
+    }
+
+    pub fn is_integer(&self) -> bool {
+        matches!(self, Number::Int(_) | Number::Big(_))
+    }
+
+    fn is_float(&self) -> bool {
+        matches!(self, Number::Float(_))
+    }
+
+    fn to_big(&self) -> Option<BigInt> {
+        match self {
+            Number::Int(v) => Some(BigInt::from_i128(*v)),
+            Number::Big(v) => Some(v.clone()),
+            _ => None,
+        }
+    }
+
+    fn to_big_ratio(&self) -> Option<(BigInt, BigInt)> {
+        match self {
+            Number::Ratio(n, d) => Some((BigInt::from_i128(*n), BigInt::from_i128(*d))),
+            v => v.to_big().map(|v| (v, BigInt::from_i128(1))),
+        }
+    }
+
+    // Shared promotion logic for the four arithmetic operations: i128 fast
+    // path, then floats, then exact arithmetic on BigInt fractions
+    fn arith(
+        self,
+        other: Number,
+        int_op: fn(i128, i128) -> Option<i128>,
+        exact_op: fn(&BigInt, &BigInt, &BigInt, &BigInt) -> (BigInt, BigInt),
+        float_op: fn(f64, f64) -> f64,
+    ) -> Result<Number, NumberError> {
+        if let (Number::Int(a), Number::Int(b)) = (&self, &other) {
+            if let Some(v) = int_op(*a, *b) {
+                return Ok(Number::Int(v));
+            }
+        }
+        if self.is_float() || other.is_float() {
+            return Ok(Number::Float(float_op(self.to_f64(), other.to_f64())));
+        }
+        let (an, ad) = self.to_big_ratio().unwrap();
+        let (bn, bd) = other.to_big_ratio().unwrap();
+        let (num, den) = exact_op(&an, &ad, &bn, &bd);
+        Number::big_ratio(num, den)
+    }
+
+    pub fn checked_add(self, other: Number) -> Result<Number, NumberError> {
+        self.arith(
+            other,
+            i128::checked_add,
+            |an, ad, bn, bd| (an.mul(bd).add(&bn.mul(ad)), ad.mul(bd)),
+            |a, b| a + b,
+        )
+    }
+
+    pub fn checked_sub(self, other: Number) -> Result<Number, NumberError> {
+        self.arith(
+            other,
+            i128::checked_sub,
+            |an, ad, bn, bd| (an.mul(bd).sub(&bn.mul(ad)), ad.mul(bd)),
+            |a, b| a - b,
+        )
+    }
+
+    pub fn checked_mul(self, other: Number) -> Result<Number, NumberError> {
+        self.arith(
+            other,
+            i128::checked_mul,
+            |an, ad, bn, bd| (an.mul(bn), ad.mul(bd)),
+            |a, b| a * b,
+        )
+    }
+
+    // Exact division never truncates: (/ 1 3) is 1/3, not 0. Only exact
+    // division by zero is an error, floats follow IEEE 754.
+    pub fn checked_div(self, other: Number) -> Result<Number, NumberError> {
+        self.arith(
+            other,
+            |a, b| match a.checked_rem(b) {
+                Some(0) => a.checked_div(b),
+                _ => None,
+            },
+            |an, ad, bn, bd| (an.mul(bd), ad.mul(bn)),
+            |a, b| a / b,
+        )
+    }
+
+    // Integer division rounding towards zero, both args must be integers
+    pub fn checked_quotient(self, other: Number) -> Result<Number, NumberError> {
+        if let (Number::Int(a), Number::Int(b)) = (&self, &other) {
+            if let Some(v) = a.checked_div(*b) {
+                return Ok(Number::Int(v));
+            }
+        }
+        let (a, b) = (self.to_big().unwrap(), other.to_big().unwrap());
+        match a.divrem(&b) {
+            Some((q, _)) => Ok(Number::big(q)),
+            None => Err(NumberError::DivisionByZero),
+        }
+    }
+
+    // Integer modulo, the result has the sign of the divisor
+    pub fn checked_modulo(self, other: Number) -> Result<Number, NumberError> {
+        if let (Number::Int(a), Number::Int(b)) = (&self, &other) {
+            if let Some(v) = a.checked_rem(*b) {
+                return Ok(Number::Int(if v != 0 && (v < 0) != (*b < 0) { v + b } else { v }));
+            }
+        }
+        let (a, b) = (self.to_big().unwrap(), other.to_big().unwrap());
+        match a.divrem(&b) {
+            Some((_, r)) if !r.is_zero() && r.is_negative() != b.is_negative() => {
+                Ok(Number::big(r.add(&b)))
+            }
+            Some((_, r)) => Ok(Number::big(r)),
+            None => Err(NumberError::DivisionByZero),
+        }
+    }
+
+    pub fn checked_pow(self, exp: Number) -> Result<Number, NumberError> {
+        if self.is_float() || matches!(exp, Number::Ratio(..) | Number::Float(_)) {
+            return Ok(Number::Float(self.to_f64().powf(exp.to_f64())));
+        }
+        // 0, 1 and -1 stay small whatever the exponent
+        match self {
+            Number::Int(0) if exp.num_cmp(&Number::Int(0)) == Some(Ordering::Less) => {
+                return Err(NumberError::DivisionByZero)
+            }
+            Number::Int(0) if exp != Number::Int(0) => return Ok(Number::Int(0)),
+            Number::Int(1) => return Ok(Number::Int(1)),
+            Number::Int(-1) => {
+                let odd = exp.checked_modulo(Number::Int(2))? == Number::Int(1);
+                return Ok(Number::Int(if odd { -1 } else { 1 }));
+            }
+            _ => (),
+        }
+        let e = match exp {
+            Number::Int(e) => e,
+            _ => return Err(NumberError::Overflow),
+        };
+        if e < 0 {
+            let v = self.checked_pow(Number::Int(e.checked_neg().ok_or(NumberError::Overflow)?))?;
+            return Number::Int(1).checked_div(v);
+        }
+        let e = u32::try_from(e).map_err(|_| NumberError::Overflow)?;
+        let (num, den) = self.to_big_ratio().unwrap();
+        // Refused up front, computing a result this big would take ages
+        if num.bits().max(den.bits()) * e as u64 > MAX_POW_BITS {
+            return Err(NumberError::Overflow);
+        }
+        Number::big_ratio(num.pow(e), den.pow(e))
+    }
+
+    // Always non-negative, both args must be integers
+    pub fn checked_gcd(self, other: Number) -> Result<Number, NumberError> {
+        if let (Number::Int(a), Number::Int(b)) = (&self, &other) {
+            let g = gcd(a.unsigned_abs(), b.unsigned_abs());
+            if g <= i128::MAX as u128 {
+                return Ok(Number::Int(g as i128));
+            }
+        }
+        let (a, b) = (self.to_big().unwrap(), other.to_big().unwrap());
+        Ok(Number::big(a.gcd(&b)))
+    }
+
+    // Numeric comparison across the tower, None if either side is NaN
+    pub fn num_cmp(&self, other: &Number) -> Option<Ordering> {
+        if let (Number::Int(a), Number::Int(b)) = (self, other) {
+            return Some(a.cmp(b));
+        }
+        if self.is_float() || other.is_float() {
+            return self.to_f64().partial_cmp(&other.to_f64());
+        }
+        let (an, ad) = self.to_big_ratio().unwrap();
+        let (bn, bd) = other.to_big_ratio().unwrap();
+        Some(an.mul(&bd).cmp(&bn.mul(&ad)))
+    }
+}
+
+impl FromStr for Number {
+    type Err = NumberParseError;
+
+    fn from_str(s: &str) -> Result<Number, NumberParseError> {
+        match s {
+            "+inf.0" => return Ok(Number::Float(f64::INFINITY)),
+            "-inf.0" => return Ok(Number::Float(f64::NEG_INFINITY)),
+            "+nan.0" | "-nan.0" => return Ok(Number::Float(f64::NAN)),
+            _ => (),
+        }
+        if let Some(i) = s.find('/') {
+            let num: BigInt = s[..i].parse().map_err(|_| NumberParseError)?;
+            let den: BigInt = s[i + 1..].parse().map_err(|_| NumberParseError)?;
+            if den.is_zero() || den.is_negative() || s[i + 1..].starts_with('+') {
+                return Err(NumberParseError);
+            }
+            return Number::big_ratio(num, den).map_err(|_| NumberParseError);
+        }
+        if s.contains(['.', 'e', 'E']) {
+            // Rust's float parser also accepts "inf" and "nan", those are
+            // spelled +inf.0 and +nan.0 here
+            if !s.contains(|c: char| c.is_ascii_digit()) {
+                return Err(NumberParseError);
+            }
+            return s.parse().map(Number::Float).map_err(|_| NumberParseError);
+        }
+        s.parse().map(Number::big).map_err(|_| NumberParseError)
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Number::Int(v) => write!(f, "{}", v),
+            Number::Big(v) => write!(f, "{}", v),
+            Number::Ratio(n, d) => write!(f, "{}/{}", n, d),
+            Number::Float(v) if v.is_nan() => write!(f, "+nan.0"),
+            Number::Float(v) if v.is_infinite() && *v > 0.0 => write!(f, "+inf.0"),
+            Number::Float(v) if v.is_infinite() => write!(f, "-inf.0"),
+            // Keep a fractional part or an exponent so that the value reads
+            // back as a float
+            Number::Float(v) if *v != 0.0 && (v.abs() >= 1e16 || v.abs() < 1e-5) => {
+                write!(f, "{:e}", v)
+            }
+            Number::Float(v) if v.fract() == 0.0 => write!(f, "{:.1}", v),
+            Number::Float(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn number_parse_works() {
+        assert_eq!("42".parse(), Ok(Number::Int(42)));
+        assert_eq!("-42".parse(), Ok(Number::Int(-42)));
+        assert_eq!("3.5".parse(), Ok(Number::Float(3.5)));
+        assert_eq!("1e-9".parse(), Ok(Number::Float(1e-9)));
+        assert_eq!("2/4".parse(), Ok(Number::Ratio(1, 2)));
+        assert_eq!("-6/3".parse(), Ok(Number::Int(-2)));
+        assert_eq!("-inf.0".parse(), Ok(Number::Float(f64::NEG_INFINITY)));
+        assert!(matches!("+nan.0".parse(), Ok(Number::Float(v)) if v.is_nan()));
+        assert_eq!("1/0".parse::<Number>(), Err(NumberParseError));
+        assert_eq!("1/-2".parse::<Number>(), Err(NumberParseError));
+        assert_eq!("1..2".parse::<Number>(), Err(NumberParseError));
+        assert_eq!("inf".parse::<Number>(), Err(NumberParseError));
+        assert_eq!(
+            "-1267650600228229401496703205376".parse::<Number>().unwrap().to_string(),
+            "-1267650600228229401496703205376"
+        );
+        assert_eq!(
+            "1267650600228229401496703205376/1267650600228229401496703205374".parse(),
+            Ok(Number::Ratio(633825300114114700748351602688, 633825300114114700748351602687))
+        );
+    }
+
+    #[test]
+    fn number_promotion_works() {
+        assert_eq!(Number::Int(1).checked_add(Number::Float(2.5)), Ok(Number::Float(3.5)));
+        assert_eq!(Number::Int(1).checked_div(Number::Int(3)), Ok(Number::Ratio(1, 3)));
+        assert_eq!(Number::Int(6).checked_div(Number::Int(3)), Ok(Number::Int(2)));
+        assert_eq!(Number::Ratio(1, 3).checked_add(Number::Ratio(2, 3)), Ok(Number::Int(1)));
+        assert_eq!(Number::Ratio(1, 2).checked_mul(Number::Float(3.0)), Ok(Number::Float(1.5)));
+        assert_eq!(Number::Int(1).checked_sub(Number::Ratio(1, 2)), Ok(Number::Ratio(1, 2)));
+    }
+
+    #[test]
+    fn number_checked_works() {
+        let min = Number::Int(i128::MIN);
+        assert_eq!(Number::Int(1).checked_div(Number::Int(0)), Err(NumberError::DivisionByZero));
+        assert_eq!(Number::Ratio(1, 2).checked_div(Number::Int(0)), Err(NumberError::DivisionByZero));
+        assert_eq!(min.clone().checked_div(min.clone()), Ok(Number::Int(1)));
+        assert_eq!(Number::Int(0).checked_div(min.clone()), Ok(Number::Int(0)));
+        assert_eq!(min.checked_div(Number::Int(2)), Ok(Number::Int(i128::MIN / 2)));
+        assert_eq!(Number::Ratio(1, 2).checked_add(Number::Ratio(i128::MAX, 3)), Err(NumberError::Overflow));
+        assert_eq!(Number::Int(1).checked_div(Number::Float(0.0)), Ok(Number::Float(f64::INFINITY)));
+    }
+
+    #[test]
+    fn number_bignum_promotion_works() {
+        let (min, max) = (Number::Int(i128::MIN), Number::Int(i128::MAX));
+        let big = |s: &str| Number::Big(s.parse().unwrap());
+        let max_plus_one = big("170141183460469231731687303715884105728");
+        assert_eq!(max.clone().checked_add(Number::Int(1)), Ok(max_plus_one.clone()));
+        assert_eq!(min.clone().checked_div(Number::Int(-1)), Ok(max_plus_one.clone()));
+        assert_eq!(Number::Int(0).checked_sub(min.clone()), Ok(max_plus_one.clone()));
+        assert_eq!(max_plus_one.clone().checked_sub(Number::Int(1)), Ok(max.clone()));
+        assert_eq!(
+            max.clone().checked_mul(max.clone()),
+            Ok(big("28948022309329048855892746252171976962977213799489202546401021394546514198529"))
+        );
+        assert_eq!(max_plus_one.clone().checked_div(Number::Int(-2)), Ok(Number::Int(i128::MIN / 2)));
+        assert_eq!(Number::Int(1).checked_div(max_plus_one.clone()), Err(NumberError::Overflow));
+        assert_eq!(max_plus_one.clone().checked_div(Number::Int(0)), Err(NumberError::DivisionByZero));
+        assert_eq!(
+            max_plus_one.num_cmp(&max),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(Number::Ratio(1, 3).num_cmp(&Number::Float(0.3)), Some(Ordering::Greater));
+        assert_eq!(Number::Float(f64::NAN).num_cmp(&Number::Int(1)), None);
+    }
+
+    #[test]
+    fn number_integer_ops_works() {
+        let n = Number::Int;
+        assert_eq!(n(-7).checked_quotient(n(2)), Ok(n(-3)));
+        assert_eq!(n(-7).checked_modulo(n(2)), Ok(n(1)));
+        assert_eq!(n(7).checked_modulo(n(-2)), Ok(n(-1)));
+        assert_eq!(n(i128::MIN).checked_modulo(n(-1)), Ok(n(0)));
+        assert_eq!(n(7).checked_modulo(n(0)), Err(NumberError::DivisionByZero));
+        assert_eq!(n(2).checked_pow(n(-2)), Number::ratio(1, 4));
+        assert_eq!(Number::Ratio(2, 3).checked_pow(n(3)), Number::ratio(8, 27));
+        assert_eq!(n(0).checked_pow(n(-1)), Err(NumberError::DivisionByZero));
+        assert_eq!(n(4).checked_pow(Number::Float(0.5)), Ok(Number::Float(2.0)));
+        assert_eq!(n(2).checked_pow(n(130)).unwrap().to_string(), "1361129467683753853853498429727072845824");
+        let big: Number = "10000000000000000000000000000000000000000".parse().unwrap();
+        assert_eq!(n(1).checked_pow(big.clone()), Ok(n(1)));
+        assert_eq!(n(0).checked_pow(big.clone()), Ok(n(0)));
+        assert_eq!(n(-1).checked_pow(big.clone()), Ok(n(1)));
+        assert_eq!(n(-1).checked_pow(big.clone().checked_add(n(1)).unwrap()), Ok(n(-1)));
+        assert_eq!(n(-1).checked_pow(n(-3)), Ok(n(-1)));
+        assert_eq!(n(0).checked_pow(n(0)), Ok(n(1)));
+        assert_eq!(n(2).checked_pow(big), Err(NumberError::Overflow));
+        // too big to be worth computing
+        assert_eq!(n(3).checked_pow(n(100_000_000)), Err(NumberError::Overflow));
+        assert_eq!(Number::Ratio(1, 3).checked_pow(n(1 << 20)), Err(NumberError::Overflow));
+        assert_eq!(n(2).checked_pow(n(1 << 16)).unwrap().to_string().len(), 19729);
+        assert_eq!(n(-12).checked_gcd(n(18)), Ok(n(6)));
+        assert_eq!(n(i128::MIN).checked_gcd(n(0)).unwrap().to_string(), "170141183460469231731687303715884105728");
+    }
+
+    #[test]
+    fn number_display_works() {
+        assert_eq!(Number::Float(3.0).to_string(), "3.0");
+        assert_eq!(Number::Float(0.25).to_string(), "0.25");
+        assert_eq!(Number::Float(1e-9).to_string(), "1e-9");
+        assert_eq!(Number::Ratio(-1, 3).to_string(), "-1/3");
+        assert_eq!(Number::Float(f64::INFINITY).to_string(), "+inf.0");
+    }
+}
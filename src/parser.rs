@@ -1,23 +1,51 @@
-use crate::ast::{Number, Qexpr, Sexpr, Symbol, ValType, Val, AST};
+use crate::ast::{Number, Pair, Qexpr, Sexpr, Str, Symbol, ValType, Val, AST};
+use std::fmt;
 use std::rc::Rc;
-use crate::token::{Token, Tokenizer2};
+use crate::span::Span;
+use crate::token::{Token, Tokenizer2, TokenizerError};
 use std::iter::Iterator;
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug)]
-enum ErrorKind {
-//    TokenizerError,
+pub enum ErrorKind {
+    TokenizerError(TokenizerError),
     ParserError,
     ParseSexprError,
-    IntegerParseError,
-    ExprParseError,
+    NumberParseError,
+    StringParseError,
+    ExprParseError(String),
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::TokenizerError(e) => write!(f, "{}", e),
+            ErrorKind::ParserError => write!(f, "unexpected end of input"),
+            ErrorKind::ParseSexprError => write!(f, "unclosed list"),
+            ErrorKind::NumberParseError => write!(f, "malformed number"),
+            ErrorKind::StringParseError => write!(f, "malformed escape in string literal"),
+            ErrorKind::ExprParseError(v) => write!(f, "unexpected '{}'", v),
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct ParserError {
-    error: ErrorKind,
+    pub error: ErrorKind,
+    pub span: Span,
+}
+
+impl From<TokenizerError> for ParserError {
+    fn from(e: TokenizerError) -> Self {
+        ParserError {
+            span: e.span,
+            error: ErrorKind::TokenizerError(e),
+        }
+    }
 }
 
 pub struct Parser<'a> {
+    input: &'a str,
     t: std::iter::Peekable<Tokenizer2<'a>>,
 }
 
@@ -26,63 +54,160 @@ impl<'a> Parser<'a> {
         let t: Tokenizer2<'a> = Tokenizer2::new(input);
 
         Parser {
+            input,
             t: t.peekable(),
         }
     }
 
-    fn parse_sexpr(&mut self) -> Result<Sexpr, ParserError> {
+    fn peek(&mut self) -> Result<Option<&(Token<'a>, Span)>, ParserError> {
+        match self.t.peek() {
+            Some(Ok(v)) => Ok(Some(v)),
+            Some(Err(e)) => Err(ParserError::from(e.clone())),
+            None => Ok(None),
+        }
+    }
+
+    fn next(&mut self) -> Result<Option<(Token<'a>, Span)>, ParserError> {
+        match self.t.next() {
+            Some(v) => Ok(Some(v?)),
+            None => Ok(None),
+        }
+    }
+
+    fn unexpected(token: &Token, span: Span) -> ParserError {
+        ParserError {
+            error: ErrorKind::ExprParseError(token.to_string()),
+            span,
+        }
+    }
+
+    fn unexpected_eof(&self) -> ParserError {
+        ParserError {
+            error: ErrorKind::ParserError,
+            span: Span::eof(self.input),
+        }
+    }
+
+    // Parses a sequence of expressions up to the `close` token, used both
+    // for (...) and {...}. A dot before the last expression makes it a
+    // dotted list: (a b . c).
+    fn parse_sexpr(&mut self, close: Token<'static>) -> Result<ValType, ParserError> {
         // Pass lparen
-        self.t.next();
+        let open = self.next()?.unwrap().1;
+        let unclosed = ParserError {
+            error: ErrorKind::ParseSexprError,
+            span: open,
+        };
         let mut ret: Vec<Val> = Vec::new();
         loop {
-            let token = self.t.peek();
+            let (token, span) = match self.peek()? {
+                Some((t, span)) => (t, *span),
+                None => return Err(unclosed),
+            };
             match token {
-                Some(token2) => match token2 {
-                    Ok(Token::RParen) => {
-                        self.t.next();
-                        return Ok(Sexpr::new(ret));
-                    }
-                    _ => {
-                        let val = self.parse_expr()?;
-                        ret.push(Rc::new(val));
-                    }
-                },
-                None => {
-                    return Err(ParserError {
-                        error: ErrorKind::ParseSexprError,
-                    });
+                t if *t == close => {
+                    self.t.next();
+                    return Ok(ValType::Sexpr(Sexpr::new_spanned(ret, open.to(span))));
+                }
+                Token::Dot if !ret.is_empty() => {
+                    self.t.next();
+                    let tail = Rc::new(self.parse_expr()?);
+                    return match self.next()? {
+                        Some((t, end)) if t == close => {
+                            let mut ret = Pair::new_list(ret, tail);
+                            if let ValType::Sexpr(v) = &mut ret {
+                                v.span = Some(open.to(end));
+                            }
+                            Ok(ret)
+                        }
+                        Some((t, span)) => Err(Self::unexpected(&t, span)),
+                        None => Err(unclosed),
+                    };
+                }
+                _ => {
+                    let val = self.parse_expr()?;
+                    ret.push(Rc::new(val));
                 }
             }
         }
     }
-    //Err(ErrorKind::ParseSexprError)
 
     fn parse_qexpr(&mut self) -> Result<Qexpr, ParserError> {
         self.t.next();
-        Ok(Qexpr::new(self.parse_sexpr()?))
-    }
-
-    fn parse_integer(&mut self) -> Result<Number, ParserError> {
-        let sym = self.t.next().unwrap().unwrap();
-        match sym {
-            Token::Number(num) => {
-                let num = num.parse();
-                match num {
-                    Ok(num) => Ok(Number::new(num)),
-                    Err(_) => Err(ParserError {
-                        error: ErrorKind::IntegerParseError,
-                    }),
+        Ok(Qexpr::new(self.parse_expr()?))
+    }
+
+    // {a b c} is a shorthand for '(a b c)
+    fn parse_braces(&mut self) -> Result<Qexpr, ParserError> {
+        Ok(Qexpr::new(self.parse_sexpr(Token::RBrace)?))
+    }
+
+    fn parse_number(&mut self) -> Result<Number, ParserError> {
+        match self.next()? {
+            Some((Token::Number(num), span)) => match num.parse() {
+                Ok(num) => Ok(num),
+                Err(_) => Err(ParserError {
+                    error: ErrorKind::NumberParseError,
+                    span,
+                }),
+            },
+            _ => panic!("parse_number called on a non-number token"),
+        }
+    }
+
+    fn unescape(raw: &str) -> Option<String> {
+        let mut ret = String::with_capacity(raw.len());
+        let mut chars = raw.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                ret.push(c);
+                continue;
+            }
+            match chars.next()? {
+                'n' => ret.push('\n'),
+                't' => ret.push('\t'),
+                'r' => ret.push('\r'),
+                '0' => ret.push('\0'),
+                '"' => ret.push('"'),
+                '\\' => ret.push('\\'),
+                'u' => {
+                    if chars.next()? != '{' {
+                        return None;
+                    }
+                    let mut code = String::new();
+                    loop {
+                        match chars.next()? {
+                            '}' => break,
+                            v => code.push(v),
+                        }
+                    }
+                    if code.is_empty() || code.len() > 6 {
+                        return None;
+                    }
+                    ret.push(std::char::from_u32(u32::from_str_radix(&code, 16).ok()?)?);
                 }
+                _ => return None,
             }
-            _ => Err(ParserError {
-                error: ErrorKind::IntegerParseError,
-            }),
+        }
+        Some(ret)
+    }
+
+    fn parse_string(&mut self) -> Result<Str, ParserError> {
+        match self.next()? {
+            Some((Token::Literal(v), span)) => match Self::unescape(v) {
+                Some(v) => Ok(Str::new(v)),
+                None => Err(ParserError {
+                    error: ErrorKind::StringParseError,
+                    span,
+                }),
+            },
+            _ => panic!("parse_string called on a non-literal token"),
         }
     }
 
     fn parse_symbol(&mut self) -> Result<Symbol, ParserError> {
-        if let Token::Symbol(v) = self.t.next().unwrap().unwrap() {
-            Ok(Symbol::new(v.to_owned()))
+        if let Some((Token::Symbol(v), span)) = self.next()? {
+            Ok(Symbol::new_spanned(v.to_owned(), span))
         } else {
             panic!("Something gone wrong!");
         }
@@ -92,21 +217,33 @@ impl<'a> Parser<'a> {
         Ok(AST::new(Rc::new(self.parse_expr()?)))
     }
 
+    // Parses every top-level form in the input, e.g. a whole script file
+    pub fn parse_program(&mut self) -> Result<Vec<AST>, ParserError> {
+        let mut ret = Vec::new();
+        while self.peek()?.is_some() {
+            ret.push(AST::new(Rc::new(self.parse_expr()?)));
+        }
+        Ok(ret)
+    }
+
     pub fn parse_expr(&mut self) -> Result<ValType, ParserError> {
-        if let Some(token) = self.t.peek() {
-            match token {
-                Ok(Token::LParen) => Ok(ValType::Sexpr(self.parse_sexpr()?)),
-                Ok(Token::Quote) => Ok(ValType::Qexpr(self.parse_qexpr()?)),
-                Ok(Token::Number(_)) => Ok(ValType::Number(self.parse_integer()?)),
-                Ok(Token::Symbol(_)) => Ok(ValType::Symbol(self.parse_symbol()?)),
-                _ => Err(ParserError {
-                    error: ErrorKind::ExprParseError,
-                }),
+        let (token, span) = match self.peek()? {
+            Some((t, span)) => (t, *span),
+            None => return Err(self.unexpected_eof()),
+        };
+        match token {
+            Token::LParen => self.parse_sexpr(Token::RParen),
+            Token::LBrace => Ok(ValType::Qexpr(self.parse_braces()?)),
+            Token::Quote => Ok(ValType::Qexpr(self.parse_qexpr()?)),
+            Token::Number(_) => Ok(ValType::Number(self.parse_number()?)),
+            Token::Symbol(_) => Ok(ValType::Symbol(self.parse_symbol()?)),
+            Token::Literal(_) => Ok(ValType::Str(self.parse_string()?)),
+            Token::Bool(v) => {
+                let v = *v;
+                self.t.next();
+                Ok(ValType::Bool(v))
             }
-        } else {
-            Err(ParserError {
-                error: ErrorKind::ParserError,
-            })
+            t => Err(Self::unexpected(t, span)),
         }
     }
 }
@@ -114,12 +251,45 @@ impl<'a> Parser<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ast::eval_program;
+    use crate::env::Env;
+    use crate::testing::{eval, num, string, Session};
+
     #[test]
-    fn eval_simple_ast_works() {
-        let input = String::from("(+ 2 2)");
-        let out = Parser::new(&input).parse().unwrap().eval().unwrap();
+    fn parse_program_works() {
+        let program = Parser::new("(setq '(x) 2)\n\t(setq '(y) 3)\r\n(* x y)  ").parse_program().unwrap();
+        assert_eq!(program.len(), 3);
+        let env = Rc::new(Env::new(None));
+        assert_eq!(eval_program(&program, env).unwrap(), num(6));
+
+        let env = Rc::new(Env::new(None));
+        assert_eq!(eval_program(&[], env).unwrap(), Rc::new(ValType::Nil));
+        assert!(Parser::new("(+ 1 2) (+ 1").parse_program().is_err());
+        assert!(Parser::new("1 )").parse_program().is_err());
 
-        assert_eq!(out, ValType::Number(Number::new(4)));
+        // evaluation stops at the first error
+        let s = Session::default();
+        assert!(s.run("(setq '(x) 1) (/ 1 0) (setq '(x) 2)").is_err());
+        assert_eq!(s.env.get("x"), Some(num(1)));
     }
 
+    #[test]
+    fn parse_lists_works() {
+        assert_eq!(eval("{1 2 3}"), eval("'(1 2 3)"));
+        assert_eq!(eval("'(1 . 2)").to_string(), "(1 . 2)");
+        assert_eq!(eval("'(1 2 . 3)").to_string(), "(1 2 . 3)");
+        assert_eq!(eval("'(1 . (2 3))").to_string(), "(1 2 3)");
+        assert_eq!(eval("'(a . .5)").to_string(), "(a . 0.5)");
+        for input in ["{1 2)", "(1 2}", "(. 1)", "(1 . 2 3)", "(1 .)", "1.2.3"] {
+            assert!(Parser::new(input).parse().is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn parse_string_escapes_works() {
+        assert_eq!(eval(r#""a\tb\n\"c\"\\""#), string("a\tb\n\"c\"\\"));
+        assert_eq!(eval(r#""\u{3bb} \u{1F600}""#), string("λ 😀"));
+        assert!(Parser::new(r#""\q""#).parse().is_err());
+        assert!(Parser::new(r#""\u{110000}""#).parse().is_err());
+    }
 }
@@ -0,0 +1,310 @@
+// Printing values as Lisp.
+//
+// `Style::Write` output reads back as the same value, strings are printed
+// as literals: "a\nb". It is what Display on values does. `Style::Display`
+// is for output meant for people, strings are printed as their contents.
+//
+// Round trip contract: for any value the reader can produce (numbers,
+// strings, bools, symbols, lists, dotted lists and quoted forms) parsing
+// the Write output, flat or pretty, gives back an equal value. Functions,
+// nil, symbols the reader can't read (e.g. from string->symbol) and pairs
+// built at runtime that end in a list or loop back into themselves have
+// no such guarantee.
+//
+// `pretty` breaks lists that don't fit in the given width across lines:
+//
+//   (define-thing foo
+//                 (1 2 3)
+//                 '(a b c))
+//
+use crate::ast::{Pair, Val, ValType};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Style {
+    Write,
+    Display,
+}
+
+// Pairs being printed, from the outermost in. Pairs can be made to point
+// back at themselves with set-car! and set-cdr!, such a pair is printed as
+// ... where it shows up inside itself.
+type Path = Vec<*const Pair>;
+
+// One line rendering of `v`
+pub fn print(v: &ValType, style: Style) -> String {
+    print_in(v, style, &mut Path::new())
+}
+
+pub(crate) fn print_pair(p: &Pair, style: Style) -> String {
+    pair(p, style, &mut Path::new())
+}
+
+fn print_in(v: &ValType, style: Style, path: &mut Path) -> String {
+    match (v, style) {
+        (ValType::Str(v), Style::Display) => v.val.to_owned(),
+        (ValType::Sexpr(v), _) => list(&v.val, None, style, path),
+        (ValType::Qexpr(v), _) => format!("'{}", print_in(v.quoted(), style, path)),
+        (ValType::Pair(v), _) => pair(v, style, path),
+        (v, _) => v.to_string(),
+    }
+}
+
+fn pair(p: &Pair, style: Style, path: &mut Path) -> String {
+    if path.contains(&(p as *const Pair)) {
+        return "...".to_owned();
+    }
+    path.push(p);
+    let (items, tail) = p.items();
+    let ret = list(&items, tail.as_ref(), style, path);
+    path.pop();
+    ret
+}
+
+fn list(items: &[Val], tail: Option<&Val>, style: Style, path: &mut Path) -> String {
+    let mut ret: Vec<String> = items.iter().map(|v| print_in(v, style, path)).collect();
+    match tail.map(|v| &**v) {
+        // Pair::items only ends in a pair where the chain loops
+        Some(ValType::Pair(_)) => ret.push(". ...".to_owned()),
+        Some(v) => ret.push(format!(". {}", print_in(v, style, path))),
+        None => (),
+    }
+    format!("({})", ret.join(" "))
+}
+
+// Lines are kept within `width` chars where possible, atoms longer than
+// that are never split
+pub fn pretty(v: &ValType, width: usize, style: Style) -> String {
+    layout(v, 0, width, style, &mut Path::new())
+}
+
+// `v` printed starting at column `col`, continuation lines are indented
+// relative to it
+fn layout(v: &ValType, col: usize, width: usize, style: Style, path: &mut Path) -> String {
+    let flat = print_in(v, style, path);
+    if col + flat.chars().count() <= width {
+        return flat;
+    }
+    match v {
+        ValType::Sexpr(l) if !l.val.is_empty() => layout_list(&l.val, None, col, width, style, path),
+        ValType::Pair(p) if !path.contains(&(p as *const Pair)) => {
+            path.push(p);
+            let (items, tail) = p.items();
+            let ret = layout_list(&items, tail.as_ref(), col, width, style, path);
+            path.pop();
+            ret
+        }
+        ValType::Qexpr(q) => format!("'{}", layout(q.quoted(), col + 1, width, style, path)),
+        _ => flat,
+    }
+}
+
+// Calls keep the first arg next to the head and align the rest under it,
+// other lists put one element per line after the '('
+fn layout_list(
+    items: &[Val],
+    tail: Option<&Val>,
+    col: usize,
+    width: usize,
+    style: Style,
+    path: &mut Path,
+) -> String {
+    let (mut ret, rest, indent) = match &*items[0] {
+        ValType::Symbol(s) if items.len() > 1 => {
+            let indent = col + s.val.chars().count() + 2;
+            let first = layout(&items[1], indent, width, style, path);
+            (format!("({} {}", s.val, first), &items[2..], indent)
+        }
+        _ => (format!("({}", layout(&items[0], col + 1, width, style, path)), &items[1..], col + 1),
+    };
+    let pad = " ".repeat(indent);
+    for v in rest {
+        ret += &format!("\n{}{}", pad, layout(v, indent, width, style, path));
+    }
+    match tail.map(|v| &**v) {
+        Some(ValType::Pair(_)) => ret += &format!("\n{}. ...", pad),
+        Some(v) => ret += &format!("\n{}. {}", pad, layout(v, indent + 2, width, style, path)),
+        None => (),
+    }
+    ret + ")"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{Number, Pair, Qexpr, Sexpr, Str, Symbol};
+    use crate::parser::Parser;
+    use crate::testing::{eval, Session};
+    use std::rc::Rc;
+
+    // xorshift64*, plenty for test data and keeps failures reproducible
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 >> 12;
+            self.0 ^= self.0 << 25;
+            self.0 ^= self.0 >> 27;
+            self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+
+        fn pick<'a, T>(&mut self, v: &'a [T]) -> &'a T {
+            &v[self.below(v.len())]
+        }
+    }
+
+    fn gen_number(rng: &mut Rng) -> Number {
+        match rng.below(6) {
+            0 => Number::new(rng.below(100) as i128 - 50),
+            1 => Number::new(rng.next() as i64 as i128 * rng.next() as i128),
+            2 => {
+                let digits: String = (0..20 + rng.below(40))
+                    .map(|_| (b'1' + rng.below(9) as u8) as char)
+                    .collect();
+                format!("{}{}", rng.pick(&["", "-"]), digits).parse().unwrap()
+            }
+            3 => Number::ratio(rng.next() as i64 as i128, rng.below(1000) as i128 + 1).unwrap(),
+            4 => Number::float(*rng.pick(&[0.0, -0.0, 0.1, 1e16, 1e-5, 9.99e-6, f64::INFINITY, f64::NEG_INFINITY])),
+            _ => {
+                let v = f64::from_bits(rng.next());
+                Number::float(if v.is_nan() { 0.5 } else { v })
+            }
+        }
+    }
+
+    fn gen_string(rng: &mut Rng) -> String {
+        let chars = ['a', 'Z', ' ', '"', '\\', '\'', '\n', '\t', '\r', '\0', '\u{7f}', '\u{301}', 'λ', '😀', '(', ';'];
+        (0..rng.below(8)).map(|_| *rng.pick(&chars)).collect()
+    }
+
+    fn gen_symbol(rng: &mut Rng) -> String {
+        let first = ["a", "Z", "λ", "→", "_", "+", "-", "*", "/", "\\", "<=", "!", "&", "?", ":k"];
+        let rest = ['a', 'Q', 'é', '変', '0', '9', '-', '+', '*', '/', '?', '!', '_', '=', '>', ':'];
+        let mut ret = rng.pick(&first).to_string();
+        ret.extend((0..rng.below(5)).map(|_| *rng.pick(&rest)));
+        // +1 and -9 are numbers
+        if ret.len() > 1 && ret.as_bytes()[1].is_ascii_digit() {
+            ret.insert(1, 'a');
+        }
+        ret
+    }
+
+    fn gen_value(rng: &mut Rng, depth: usize) -> ValType {
+        let n = if depth == 0 { 4 } else { 7 };
+        match rng.below(n) {
+            0 => ValType::Number(gen_number(rng)),
+            1 => ValType::Str(Str::new(gen_string(rng))),
+            2 => ValType::Bool(rng.below(2) == 0),
+            3 => ValType::Symbol(Symbol::new(gen_symbol(rng))),
+            4 => ValType::Qexpr(Qexpr::new(gen_value(rng, depth - 1))),
+            5 => {
+                let items = (0..rng.below(5)).map(|_| Rc::new(gen_value(rng, depth - 1)));
+                ValType::Sexpr(Sexpr::new(items.collect()))
+            }
+            _ => {
+                let items = (0..1 + rng.below(4)).map(|_| Rc::new(gen_value(rng, depth - 1)));
+                // Same as the reader, a list tail makes a proper list
+                Pair::new_list(items.collect(), Rc::new(gen_value(rng, depth - 1)))
+            }
+        }
+    }
+
+    fn read(input: &str) -> ValType {
+        let mut parser = Parser::new(input);
+        let ret = parser.parse_expr().unwrap_or_else(|e| panic!("{:?} reading {}", e, input));
+        assert!(parser.parse_expr().is_err(), "trailing input in {}", input);
+        ret
+    }
+
+    #[test]
+    fn print_works() {
+        let show = |input| eval(input).to_string();
+        assert_eq!(show("(list 1 2 3)"), "(1 2 3)");
+        assert_eq!(show("'(a \"b\\n\" #t)"), "(a \"b\\n\" #t)");
+        assert_eq!(show("''(a b)"), "'(a b)");
+        assert_eq!(show("(cons 1 (cons 2 3))"), "(1 2 . 3)");
+        assert_eq!(show("(\\ '(x y) '(+ x y))"), "<lambda (x y)>");
+        assert_eq!(
+            show("(\\ '(a &optional (b 1) & r &key k) '(+ a b))"),
+            "<lambda (a &optional (b 1) & r &key k)>"
+        );
+        assert_eq!(show("+"), "<builtin +>");
+        assert_eq!(show("car"), "<builtin car>");
+        assert_eq!(show("if"), "<builtin if>");
+        assert_eq!(show("(partial + 1)"), "<builtin partial>");
+
+        let v = eval("(list \"a\" '(\"b\" . \"c\") ''\"d\")");
+        assert_eq!(print(&v, Style::Write), r#"("a" ("b" . "c") '"d")"#);
+        assert_eq!(print(&v, Style::Display), "(a (b . c) 'd)");
+        assert_eq!(print(&eval("\"x\\ty\""), Style::Display), "x\ty");
+    }
+
+    #[test]
+    fn pretty_works() {
+        let v = eval("'(define-thing foo (1 2 3) '(a b c))");
+        assert_eq!(pretty(&v, 80, Style::Write), "(define-thing foo (1 2 3) '(a b c))");
+        assert_eq!(
+            pretty(&v, 30, Style::Write),
+            "(define-thing foo\n              (1 2 3)\n              '(a b c))"
+        );
+        assert_eq!(
+            pretty(&v, 10, Style::Write),
+            "(define-thing foo
+              (1
+               2
+               3)
+              '(a b
+                  c))"
+        );
+        let v = eval("(list (list 1 2) (cons 3 4) \"five\")");
+        assert_eq!(pretty(&v, 12, Style::Write), "((1 2)\n (3 . 4)\n \"five\")");
+        assert_eq!(pretty(&v, 12, Style::Display), "((1 2)\n (3 . 4)\n five)");
+        assert_eq!(pretty(&eval("(cons 1 (cons 2 3))"), 5, Style::Write), "(1\n 2\n . 3)");
+        // atoms are never split
+        assert_eq!(pretty(&eval("\"a long string\""), 4, Style::Write), "\"a long string\"");
+    }
+
+    #[test]
+    fn print_cycles_works() {
+        let s = Session::default();
+        s.eval("(setq '(p) (cons 1 2))");
+        s.eval("(set-cdr! p p)");
+        assert_eq!(s.show("p"), "(1 . ...)");
+        assert_eq!(pretty(&s.eval("p"), 4, Style::Write), "(1\n . ...)");
+        s.eval("(setq '(q) (cons 1 (cons 2 3)))");
+        s.eval("(set-cdr! (cdr q) (cdr q))");
+        assert_eq!(s.show("q"), "(1 2 . ...)");
+        s.eval("(set-car! p p)");
+        assert_eq!(s.show("p"), "(... . ...)");
+        s.eval("(setq '(r) (cons 0 0))");
+        s.eval("(set-car! r (list 1 r))");
+        assert_eq!(s.show("r"), "((1 ...) . 0)");
+        assert_eq!(pretty(&s.eval("r"), 6, Style::Display), "((1\n  ...)\n . 0)");
+        // pairs shared without a cycle are printed in full
+        s.eval("(setq '(t) (cons 1 2))");
+        assert_eq!(s.show("(list t t)"), "((1 . 2) (1 . 2))");
+    }
+
+    #[test]
+    fn round_trip_works() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..2000 {
+            let v = gen_value(&mut rng, 4);
+            let flat = v.to_string();
+            assert_eq!(read(&flat), v, "{}", flat);
+            assert_eq!(read(&flat).to_string(), flat);
+            for width in [0, 20, 60] {
+                let text = pretty(&v, width, Style::Write);
+                assert_eq!(read(&text), v, "{}", text);
+            }
+        }
+        // NaN never equals itself
+        match read("+nan.0") {
+            ValType::Number(Number::Float(v)) => assert!(v.is_nan()),
+            v => panic!("{:?}", v),
+        }
+    }
+}
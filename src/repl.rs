@@ -1,25 +1,588 @@
 // fn repl(prompt: "λ > ") {
+use std::cell::RefCell;
+use std::fs;
 use std::io;
 use std::io::Write;
 use std::rc::Rc;
-use crate::parser::Parser;
-use crate::env::Env;
+use std::time::Instant;
+use crate::ast::{self, FuncType, Val, ValType, AST};
+use crate::builtin;
+use crate::diagnostics::{self, Diagnostic};
+use crate::editor::{Candidate, Completer, Editor, Highlight, Highlighter, History, LineReader};
+use crate::env::{Env, EnvRef};
+use crate::parser::{ErrorKind as ParserErrorKind, Parser, ParserError};
+use crate::printer::{self, Style};
+use crate::token::{ErrorKind as TokenizerErrorKind, Token, Tokenizer2};
+
+// Lines starting with one of these are commands for the REPL itself
+// rather than Lisp, they are never seen by the parser. Any other `:word`
+// is a keyword and is evaluated.
+const COMMANDS: &[&str] = &[":help", ":h", ":env", ":doc", ":type", ":time", ":load", ":reset", ":quit", ":q"];
+
+const HELP: &str = "\
+:help          show this list
+:env [all]     list the bindings made in this session, or all of them
+:doc sym       show what sym is bound to, with docs for builtins
+:type expr     show the type of expr's value
+:time expr     evaluate expr and show how long it took
+:load file     evaluate a file into the session
+:reset         drop every binding made in this session
+:quit          leave the REPL";
+
+pub struct Repl {
+    env: EnvRef,
+    prompt: String,
+    // Shown while a form spans several lines, as wide as `prompt`
+    cont: String,
+    color: bool,
+    // Lines of the form being read, for the highlighter
+    pending: Rc<RefCell<String>>,
+}
+
+impl Repl {
+    pub fn new(prompt: &str) -> Repl {
+        let width = prompt.chars().count().max(4);
+        Repl {
+            env: Rc::new(Env::new(None)),
+            prompt: prompt.to_owned(),
+            cont: format!("{:>w$} ", "...", w = width - 1),
+            color: false,
+            pending: Rc::new(RefCell::new(String::new())),
+        }
+    }
+
+    pub fn color(mut self, color: bool) -> Repl {
+        self.color = color;
+        self
+    }
+
+    // Reads forms from `lines` until EOF. Results go to `out`, diagnostics
+    // to `err`; no error ends the session. Ctrl-C drops a form that isn't
+    // finished yet.
+    pub fn run(&self, lines: &mut impl LineReader, out: &mut impl Write, err: &mut impl Write) -> io::Result<()> {
+        let mut buf = String::new();
+        loop {
+            let prompt = if buf.is_empty() { &self.prompt } else { &self.cont };
+            self.pending.replace(buf.clone());
+            match lines.read_line(prompt) {
+                Ok(Some(line)) if buf.is_empty() && is_command(&line) => {
+                    if self.command(line.trim(), out, err)? {
+                        continue;
+                    }
+                    return Ok(());
+                }
+                Ok(Some(line)) => buf += &line,
+                Ok(None) => {
+                    // Ctrl-D, leave the shell prompt on a line of its own
+                    writeln!(out)?;
+                    return Ok(());
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                    buf.clear();
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
+            buf.push('\n');
+            match Parser::new(&buf).parse_program() {
+                Err(e) if incomplete(&e) => continue,
+                Err(e) => writeln!(err, "{}", self.render(Diagnostic::from(&e), &buf))?,
+                Ok(program) => self.eval(&program, &buf, out, err)?,
+            }
+            buf.clear();
+        }
+    }
+
+    // Every form on the line is evaluated and printed, up to the first error
+    fn eval(&self, program: &[AST], input: &str, out: &mut impl Write, err: &mut impl Write) -> io::Result<()> {
+        for form in program {
+            match form.eval(Rc::clone(&self.env)) {
+                Ok(v) if *v == ValType::Nil => (),
+                Ok(v) => writeln!(out, "{}", printer::pretty(&v, 80, Style::Write))?,
+                Err(e) => return writeln!(err, "{}", self.render(Diagnostic::from(&e), input)),
+            }
+        }
+        Ok(())
+    }
+
+    // Runs a meta-command, false once the session should end
+    fn command(&self, line: &str, out: &mut impl Write, err: &mut impl Write) -> io::Result<bool> {
+        let (cmd, arg) = match line.find(char::is_whitespace) {
+            Some(i) => (&line[..i], line[i..].trim()),
+            None => (line, ""),
+        };
+        let needs_arg = matches!(cmd, ":doc" | ":type" | ":time" | ":load");
+        if needs_arg && arg.is_empty() {
+            let usage = HELP.lines().find(|l| l.starts_with(cmd)).unwrap_or(cmd);
+            writeln!(err, "usage: {}", usage.split("  ").next().unwrap_or(cmd))?;
+            return Ok(true);
+        }
+        match cmd {
+            ":help" | ":h" => writeln!(out, "{}", HELP)?,
+            ":env" => self.list_env(arg == "all", out)?,
+            ":doc" => self.doc(arg, out, err)?,
+            ":type" => {
+                if let Some(v) = self.eval_expr(arg, err)? {
+                    writeln!(out, "{}", v.type_name())?
+                }
+            }
+            ":time" => {
+                let start = Instant::now();
+                if let Some(v) = self.eval_expr(arg, err)? {
+                    let elapsed = start.elapsed();
+                    if *v != ValType::Nil {
+                        writeln!(out, "{}", printer::pretty(&v, 80, Style::Write))?;
+                    }
+                    writeln!(out, "; {:?}", elapsed)?;
+                }
+            }
+            ":load" => self.load(arg.trim_matches('"'), err)?,
+            ":reset" => self.env.reset(),
+            ":quit" | ":q" => return Ok(false),
+            _ => unreachable!("{} is not in COMMANDS", cmd),
+        }
+        Ok(true)
+    }
+
+    // Builtins are left out unless `all` is set, names rebound to another
+    // value are listed either way
+    fn list_env(&self, all: bool, out: &mut impl Write) -> io::Result<()> {
+        for (name, v) in self.env.bindings() {
+            if all || !is_builtin(&name, &v) {
+                writeln!(out, "{} = {}", name, printer::print(&v, Style::Write))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn doc(&self, name: &str, out: &mut impl Write, err: &mut impl Write) -> io::Result<()> {
+        let v = match self.env.get(name) {
+            Some(v) => v,
+            None => return writeln!(err, ":doc: unbound symbol: {}", name),
+        };
+        match (&*v, builtin::doc(name)) {
+            (_, Some(doc)) if is_builtin(name, &v) => writeln!(out, "{}", doc),
+            (ValType::Function(FuncType::Lambda(l)), _) => {
+                let params = l.signature();
+                let params = &params[1..params.len() - 1];
+                let sep = if params.is_empty() { "" } else { " " };
+                writeln!(out, "({}{}{}) lambda", name, sep, params)
+            }
+            (v, _) => writeln!(out, "{} = {} ({})", name, printer::print(v, Style::Write), v.type_name()),
+        }
+    }
+
+    // Value of the last form in `input`, None after reporting an error
+    fn eval_expr(&self, input: &str, err: &mut impl Write) -> io::Result<Option<Val>> {
+        let program = match Parser::new(input).parse_program() {
+            Ok(v) => v,
+            Err(e) => return writeln!(err, "{}", self.render(Diagnostic::from(&e), input)).map(|_| None),
+        };
+        match ast::eval_program(&program, Rc::clone(&self.env)) {
+            Ok(v) => Ok(Some(v)),
+            Err(e) => writeln!(err, "{}", self.render(Diagnostic::from(&e), input)).map(|_| None),
+        }
+    }
+
+    // Same as running a file from the command line, only into this
+    // session's env and without exiting on errors
+    fn load(&self, path: &str, err: &mut impl Write) -> io::Result<()> {
+        let input = match fs::read_to_string(path) {
+            Ok(v) => v,
+            Err(e) => return writeln!(err, ":load: {}: {}", path, e),
+        };
+        let d = match Parser::new(&input).parse_program() {
+            Ok(program) => match ast::eval_program(&program, Rc::clone(&self.env)) {
+                Ok(_) => return Ok(()),
+                Err(e) => Diagnostic::from(&e),
+            },
+            Err(e) => Diagnostic::from(&e),
+        };
+        writeln!(err, "{}", d.render(path, &input, self.color))
+    }
+
+    fn render(&self, d: Diagnostic, input: &str) -> String {
+        d.render("<repl>", input, self.color)
+    }
+}
+
+// Completes names bound in the REPL's env, builtins and special forms
+// included
+struct EnvCompleter(EnvRef);
+
+impl Completer for EnvCompleter {
+    fn complete(&self, prefix: &str) -> Vec<Candidate> {
+        self.0
+            .bindings()
+            .into_iter()
+            .filter(|(k, _)| k.starts_with(prefix))
+            .map(|(name, v)| Candidate {
+                name,
+                params: match &*v {
+                    ValType::Function(FuncType::Lambda(l)) => Some(l.signature()),
+                    _ => None,
+                },
+            })
+            .collect()
+    }
+}
+
+// Colors the line being typed. Earlier lines of an unfinished form are
+// tokenized along with it, so strings, comments and lists carry over.
+struct Syntax {
+    env: EnvRef,
+    pending: Rc<RefCell<String>>,
+}
+
+impl Highlighter for Syntax {
+    fn highlight(&self, line: &[char], cursor: Option<usize>) -> Vec<Highlight> {
+        let pending = self.pending.borrow();
+        let skip = pending.chars().count();
+        let input: String = pending.chars().chain(line.iter().copied()).collect();
+        let builtin = |name: &str| self.env.get(name).is_some_and(|v| is_builtin(name, &v));
+        let mut ret = highlight(&input, cursor.map(|v| v + skip), builtin);
+        ret.drain(..skip);
+        ret
+    }
+}
+
+// Text between tokens that isn't whitespace is a comment. Closing parens
+// without a matching opening one are errors.
+fn highlight(input: &str, cursor: Option<usize>, builtin: impl Fn(&str) -> bool) -> Vec<Highlight> {
+    let mut ret: Vec<Highlight> = input
+        .chars()
+        .map(|c| if c.is_whitespace() { Highlight::Plain } else { Highlight::Comment })
+        .collect();
+    // Char index of every byte offset a token can start or end at
+    let mut at = vec![0; input.len() + 1];
+    for (n, (i, _)) in input.char_indices().enumerate() {
+        at[i] = n;
+    }
+    at[input.len()] = ret.len();
+    let mut partner = vec![None; ret.len()];
+    let mut open: Vec<(Token, usize)> = Vec::new();
+    for t in Tokenizer2::new(input) {
+        let (kind, span) = match t {
+            Ok((token, span)) => {
+                let i = at[span.start];
+                let kind = match token {
+                    Token::Number(_) | Token::Bool(_) => Highlight::Number,
+                    Token::Literal(_) => Highlight::Str,
+                    Token::Symbol(v) if builtin(v) => Highlight::Builtin,
+                    Token::Symbol(_) => Highlight::Symbol,
+                    Token::LParen => {
+                        open.push((Token::RParen, i));
+                        Highlight::Plain
+                    }
+                    Token::LBrace => {
+                        open.push((Token::RBrace, i));
+                        Highlight::Plain
+                    }
+                    Token::RParen | Token::RBrace => match open.last() {
+                        Some((close, j)) if *close == token => {
+                            partner[i] = Some(*j);
+                            partner[*j] = Some(i);
+                            open.pop();
+                            Highlight::Plain
+                        }
+                        _ => Highlight::Error,
+                    },
+                    Token::Quote | Token::Dot | Token::EOF => Highlight::Plain,
+                };
+                (kind, span)
+            }
+            Err(e) => match e.error {
+                TokenizerErrorKind::UnterminatedLiteral => (Highlight::Str, e.span),
+                TokenizerErrorKind::UnterminatedComment | TokenizerErrorKind::MissingDatum => {
+                    (Highlight::Comment, e.span)
+                }
+                TokenizerErrorKind::GeneralError => (Highlight::Error, e.span),
+            },
+        };
+        for v in &mut ret[at[span.start]..at[span.end]] {
+            *v = kind;
+        }
+    }
+    // The paren under the cursor, or the one right before it
+    let paren = cursor.and_then(|c| {
+        let at = |i: usize| partner.get(i).copied().flatten().map(|j| (i, j));
+        at(c).or_else(|| c.checked_sub(1).and_then(at))
+    });
+    if let Some((i, j)) = paren {
+        ret[i] = Highlight::Match;
+        ret[j] = Highlight::Match;
+    }
+    ret
+}
+
+// Bound to the builtin registered under that name, not rebound by the user
+fn is_builtin(name: &str, v: &Val) -> bool {
+    match &**v {
+        ValType::Function(f) => f.builtin_name() == Some(name),
+        _ => false,
+    }
+}
+
+fn is_command(line: &str) -> bool {
+    line.split_whitespace().next().is_some_and(|cmd| COMMANDS.contains(&cmd))
+}
+
+// Errors that more input could fix: unclosed lists, strings and block
+// comments, or a quote right at the end
+fn incomplete(e: &ParserError) -> bool {
+    match &e.error {
+        ParserErrorKind::ParseSexprError | ParserErrorKind::ParserError => true,
+        ParserErrorKind::TokenizerError(e) => matches!(
+            e.error,
+            TokenizerErrorKind::UnterminatedLiteral | TokenizerErrorKind::UnterminatedComment
+        ),
+        _ => false,
+    }
+}
 
 pub fn repl(prompt: &str) {
-    let env = Env::new(None);
-    let env = Rc::new(env);
-    loop {
-        let env = Rc::clone(&env);
-        let mut input = String::new();
-        print!("{}", prompt);
-        io::stdout().flush().unwrap();
-        io::stdin().read_line(&mut input).unwrap();
-        println!("{}", input);
-        let mut parser = Parser::new(&input);
-        let out = parser.parse().unwrap();
-        let out = out.eval(env);
-
-
-        println!("{:?}", out);
+    let repl = Repl::new(prompt).color(diagnostics::use_color(&io::stderr()));
+    let history = History::default_path().map_or_else(History::new, History::load);
+    let mut editor = Editor::new(history).completer(Box::new(EnvCompleter(Rc::clone(&repl.env))));
+    if diagnostics::use_color(&io::stdout()) {
+        editor = editor.highlighter(Box::new(Syntax {
+            env: Rc::clone(&repl.env),
+            pending: Rc::clone(&repl.pending),
+        }));
+    }
+    if let Err(e) = repl.run(&mut editor, &mut io::stdout(), &mut io::stderr()) {
+        eprintln!("{}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::Plain;
+    use std::cell::RefCell;
+
+    // Output and errors of a session fed with `input`, prompts are written
+    // to the output
+    fn session(input: &str) -> (String, String) {
+        let (out, mut err) = (RefCell::new(Vec::new()), Vec::new());
+        let mut lines = Plain::new(input.as_bytes(), Shared(&out));
+        Repl::new("> ").run(&mut lines, &mut Shared(&out), &mut err).unwrap();
+        (String::from_utf8(out.take()).unwrap(), String::from_utf8(err).unwrap())
+    }
+
+    struct Shared<'a>(&'a RefCell<Vec<u8>>);
+
+    impl Write for Shared<'_> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn repl_works() {
+        assert_eq!(session(""), ("> \n".to_owned(), String::new()));
+        assert_eq!(session("(+ 1 2)\n\n(setq '(x) 4) x\n").0, "> 3\n> > 4\n> \n");
+        // lists, strings and comments can span lines
+        assert_eq!(session("(+ 1\n 2\n)\n").0, "> ... ... 3\n> \n");
+        assert_eq!(session("\"a\nb\"\n").0, "> ... \"a\\nb\"\n> \n");
+        assert_eq!(session("#| x\n|# 1\n").0, "> ... 1\n> \n");
+        assert_eq!(session("'\n(a)\n").0, "> ... (a)\n> \n");
+        // unclosed at EOF is dropped
+        assert_eq!(session("(+ 1"), ("> ... \n".to_owned(), String::new()));
+    }
+
+    #[test]
+    fn repl_errors_works() {
+        let (out, err) = session("(+ 1))\n(/ 1 0) 5\nnope\n(car {})\n2\n");
+        assert_eq!(out, "> > > > > 2\n> \n");
+        assert_eq!(
+            err,
+            "error[E0105]: unexpected ')'
+ --> <repl>:1:6
+  |
+1 | (+ 1))
+  |      ^
+  |
+  = help: this closes a list that was never opened
+error[E0202]: division by zero
+ --> <repl>:1:1
+  |
+1 | (/ 1 0) 5
+  | ^^^^^^^
+error[E0201]: unbound symbol: nope
+ --> <repl>:1:1
+  |
+1 | nope
+  | ^^^^ not found in this scope
+  |
+  = help: bind it first with (setq '(name) value)
+error[E0205]: car -- expected pair or non-empty list, got empty list
+ --> <repl>:1:1
+  |
+1 | (car {})
+  | ^^^^^^^^ got empty list here
+"
+        );
+        // forms before the failing one still take effect
+        assert_eq!(session("(setq '(y) 1) (/ 1 0)\ny\n").0, "> > 1\n> \n");
+    }
+
+    // Lines handed out in order, Err(()) is Ctrl-C
+    struct Script(Vec<Result<&'static str, ()>>);
+
+    impl LineReader for Script {
+        fn read_line(&mut self, _: &str) -> io::Result<Option<String>> {
+            match self.0.pop() {
+                Some(Ok(v)) => Ok(Some(v.to_owned())),
+                Some(Err(())) => Err(io::Error::new(io::ErrorKind::Interrupted, "")),
+                None => Ok(None),
+            }
+        }
+    }
+
+    #[test]
+    fn repl_completion_works() {
+        let repl = Repl::new("> ");
+        let mut lines = Script(vec![Ok("(setq '(my-f) (\\ '(x &optional y) '(+ x 1)))"), Ok("(setq '(my-v) 1)")]);
+        repl.run(&mut lines, &mut Vec::new(), &mut Vec::new()).unwrap();
+        let complete = |prefix| -> Vec<String> {
+            EnvCompleter(Rc::clone(&repl.env)).complete(prefix).iter().map(|v| v.to_string()).collect()
+        };
+        assert_eq!(complete("my-"), ["my-f (x &optional y)", "my-v"]);
+        assert_eq!(complete("con"), ["concat", "cond", "cons"]);
+        assert_eq!(complete("set-c"), ["set-car!", "set-cdr!"]);
+        assert!(complete("nope").is_empty());
+        // frames see their parents' bindings, their own shadow them
+        let frame = Env::new(Some(Rc::clone(&repl.env)));
+        frame.put("my-v".to_owned(), Rc::new(ValType::Bool(true)));
+        let keys = frame.keys();
+        assert!(keys.contains(&"car".to_owned()) && keys.contains(&"my-f".to_owned()));
+        assert_eq!(frame.bindings().iter().find(|(k, _)| k == "my-v").unwrap().1.to_string(), "#t");
+        assert_eq!(keys.iter().filter(|k| *k == "my-v").count(), 1);
+    }
+
+    #[test]
+    fn repl_interrupt_works() {
+        let mut lines = Script(vec![Ok("2"), Err(()), Ok("(+ 1"), Ok("1")]);
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        Repl::new("> ").run(&mut lines, &mut out, &mut err).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "1\n2\n\n");
+        assert!(err.is_empty());
+    }
+
+    #[test]
+    fn repl_commands_works() {
+        let (out, err) = session(":help\n:quit\n1\n");
+        assert_eq!(out, format!("> {}\n> ", HELP));
+        assert!(err.is_empty());
+
+        let input = "(setq '(f n car) (\\ '(a &optional b) '(+ a 1)) 2 3)
+:env
+:doc f
+:doc n
+:doc cdr
+:doc car
+:type \"s\"
+:type '(1)
+:reset
+:env
+:doc f
+";
+        let (out, err) = session(input);
+        assert_eq!(
+            out,
+            "> > car = 3\nf = <lambda (a &optional b)>\nn = 2
+> (f a &optional b) lambda
+> n = 2 (number)
+> (cdr p) rest of a pair or list
+> car = 3 (number)
+> string
+> list
+> > > > \n"
+        );
+        assert_eq!(err, ":doc: unbound symbol: f\n");
+        // `(car p) ...` is back after :reset
+        assert_eq!(session(":reset\n:doc car\n").0, "> > (car p) first element of a pair or list\n> \n");
+
+        let (out, err) = session(":time (+ 1 2)\n:type (car 1)\n:doc\n");
+        assert!(out.starts_with("> 3\n; "), "{}", out);
+        assert_eq!(
+            err,
+            "error[E0205]: car -- expected pair or non-empty list, got number
+ --> <repl>:1:1
+  |
+1 | (car 1)
+  | ^^^^^^^ got number here
+usage: :doc sym
+"
+        );
+        // commands are only recognized at the start of a form, any other
+        // `:word` is a keyword
+        assert_eq!(session("(list 1\n:a)\n").0, "> ... (1 :a)\n> \n");
+        assert_eq!(session(":key\n:help-me 1\n"), ("> :key\n> :help-me\n1\n> \n".to_owned(), String::new()));
+    }
+
+    #[test]
+    fn repl_load_works() {
+        let path = std::env::temp_dir().join(format!("lis2_load_test_{}.lisp", std::process::id()));
+        fs::write(&path, "(setq '(sq) (\\ '(x) '(* x x)))\n(sq 4)\n").unwrap();
+        let (out, err) = session(&format!(":load {}\n(sq 3)\n", path.display()));
+        assert_eq!(out, "> > 9\n> \n");
+        assert!(err.is_empty(), "{}", err);
+
+        fs::write(&path, "(sq 1)\n(car 1)\n").unwrap();
+        let err = session(&format!(":load {}\n", path.display())).1;
+        assert!(err.starts_with(&format!("error[E0201]: unbound symbol: sq\n --> {}:1:2", path.display())), "{}", err);
+        fs::remove_file(&path).unwrap();
+        assert!(session(&format!(":load {}\n", path.display())).1.starts_with(":load: "));
+    }
+
+    // One mark per char: . plain, n number, s symbol, b builtin, q string,
+    // c comment, m match, ! error
+    fn marks(pending: &str, line: &str, cursor: Option<usize>) -> String {
+        let repl = Repl::new("> ");
+        repl.pending.replace(pending.to_owned());
+        let syntax = Syntax {
+            env: Rc::clone(&repl.env),
+            pending: Rc::clone(&repl.pending),
+        };
+        let line: Vec<char> = line.chars().collect();
+        let mark = |h: &Highlight| match h {
+            Highlight::Plain => '.',
+            Highlight::Number => 'n',
+            Highlight::Symbol => 's',
+            Highlight::Builtin => 'b',
+            Highlight::Str => 'q',
+            Highlight::Comment => 'c',
+            Highlight::Match => 'm',
+            Highlight::Error => '!',
+        };
+        syntax.highlight(&line, cursor).iter().map(mark).collect()
+    }
+
+    #[test]
+    fn repl_highlight_works() {
+        assert_eq!(marks("", "(car x 1.5 #t)", None), ".bbb.s.nnn.nn.");
+        assert_eq!(marks("", "(λ \"a b\" 'y) ; hi", None), ".s.qqqqq..s..c.cc");
+        assert_eq!(marks("", "#| a |# #;(x) z", None), "cc.c.cc.ccccc.s");
+        // the paren under the cursor or right before it, and its partner
+        assert_eq!(marks("", "(+ (f) 2)", Some(0)), "mb..s..nm");
+        assert_eq!(marks("", "(+ (f) 2)", Some(6)), ".b.msm.n.");
+        assert_eq!(marks("", "(+ (f) 2)", Some(9)), "mb..s..nm");
+        assert_eq!(marks("", "(+ (f) 2)", Some(2)), ".b..s..n.");
+        // unbalanced or mismatched closing parens
+        assert_eq!(marks("", "(f)) {x)", None), ".s.!..s!");
+        assert_eq!(marks("", "(f)) }", Some(4)), ".s.!.!");
+        // earlier lines of the form close parens and finish strings
+        assert_eq!(marks("(+ 1\n", " 2)", Some(3)), ".nm");
+        assert_eq!(marks("(+ 1\n", " 2))", None), ".n.!");
+        assert_eq!(marks("\"a\n", "b\" x", None), "qq.s");
+        assert_eq!(marks("#|\n", "|# x", None), "cc.s");
+        assert_eq!(marks("", "\"open", None), "qqqqq");
+        assert_eq!(marks("", "(x @)", None), ".s.!.");
     }
 }
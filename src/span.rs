@@ -0,0 +1,101 @@
+use std::fmt;
+
+// Location of a piece of source: byte range plus the 1-based line and
+// column (in chars) of its start. Line and column are u32 to keep errors
+// that carry spans small.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: u32,
+    pub col: u32,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, line: u32, col: u32) -> Span {
+        Span {
+            start,
+            end,
+            line,
+            col,
+        }
+    }
+
+    // Empty span right after the last char of `source`
+    pub fn eof(source: &str) -> Span {
+        let line_start = source.rfind('\n').map_or(0, |v| v + 1);
+        Span {
+            start: source.len(),
+            end: source.len(),
+            line: source.matches('\n').count() as u32 + 1,
+            col: source[line_start..].chars().count() as u32 + 1,
+        }
+    }
+
+    // Span from the start of `self` up to the end of `other`
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            end: other.end,
+            ..self
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
+// Source line containing the start of `span`, together with the
+// whitespace to put before an underline of it and the underline width.
+// Multi-line spans are underlined up to the end of the first line.
+//
+// Spans don't know which input they came from (a lambda may be defined in
+// an earlier REPL line), so None is returned unless the span's line and
+// column agree with `source`.
+pub(crate) fn line_at(source: &str, span: Span) -> Option<(&str, String, usize)> {
+    let before = source.get(..span.start)?;
+    let at = Span::eof(before);
+    if (at.line, at.col) != (span.line, span.col) {
+        return None;
+    }
+    let line_start = before.rfind('\n').map_or(0, |v| v + 1);
+    let line_end = source[span.start..]
+        .find('\n')
+        .map_or(source.len(), |v| span.start + v);
+    let line = source[line_start..line_end].trim_end_matches('\r');
+    let width = source
+        .get(span.start..span.end.min(line_end))
+        .map_or(0, |v| v.chars().count())
+        .max(1);
+    let indent: String = source[line_start..span.start]
+        .chars()
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    Some((line, indent, width))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn span_works() {
+        assert_eq!(Span::new(2, 7, 2, 3).to(Span::new(9, 10, 3, 1)), Span::new(2, 10, 2, 3));
+        assert_eq!(Span::eof("(a\n  b"), Span::new(6, 6, 2, 4));
+        assert_eq!(Span::eof(""), Span::new(0, 0, 1, 1));
+    }
+
+    #[test]
+    fn line_at_works() {
+        let source = "(+ 1 2)\n  (foo))\n";
+        assert_eq!(line_at(source, Span::new(14, 15, 2, 7)), Some(("  (foo))", "      ".to_owned(), 1)));
+        // tabs are kept so the underline lines up, multi-line spans end
+        // with the first line
+        assert_eq!(line_at("\n\t(foo\n 1)", Span::new(2, 10, 2, 2)), Some(("\t(foo", "\t".to_owned(), 4)));
+        // span from some other source
+        assert_eq!(line_at("(foo)\n(bar)", Span::new(7, 8, 1, 8)), None);
+        assert_eq!(line_at("(a", Span::eof("(a")), Some(("(a", "  ".to_owned(), 1)));
+    }
+}
@@ -0,0 +1,66 @@
+// Helpers for tests that evaluate Lisp source
+use crate::ast::{eval_program, ASTError, Number, Sexpr, Str, Val, ValType};
+use crate::env::{Env, EnvRef};
+use crate::parser::Parser;
+use std::rc::Rc;
+
+// Forms evaluated one after another in the same env, later ones see what
+// earlier ones bound
+pub struct Session {
+    pub env: EnvRef,
+}
+
+impl Default for Session {
+    fn default() -> Session {
+        Session {
+            env: Rc::new(Env::new(None)),
+        }
+    }
+}
+
+impl Session {
+    // `input` has to parse, only evaluation may fail
+    pub fn run(&self, input: &str) -> Result<Val, ASTError> {
+        let program = Parser::new(input)
+            .parse_program()
+            .unwrap_or_else(|e| panic!("{:?} parsing {}", e, input));
+        eval_program(&program, Rc::clone(&self.env))
+    }
+
+    pub fn eval(&self, input: &str) -> Val {
+        self.run(input).unwrap_or_else(|e| panic!("{} evaluating {}", e, input))
+    }
+
+    pub fn err(&self, input: &str) -> ASTError {
+        self.run(input).unwrap_err()
+    }
+
+    pub fn show(&self, input: &str) -> String {
+        self.eval(input).to_string()
+    }
+}
+
+// `input` evaluated in an env of its own
+pub fn eval(input: &str) -> Val {
+    Session::default().eval(input)
+}
+
+pub fn err(input: &str) -> ASTError {
+    Session::default().err(input)
+}
+
+pub fn num(v: i128) -> Val {
+    Rc::new(ValType::Number(Number::new(v)))
+}
+
+pub fn list(v: Vec<Val>) -> Val {
+    Rc::new(ValType::Sexpr(Sexpr::new(v)))
+}
+
+pub fn string(v: &str) -> Val {
+    Rc::new(ValType::Str(Str::new(v.to_owned())))
+}
+
+pub fn boolean(v: bool) -> Val {
+    Rc::new(ValType::Bool(v))
+}
@@ -1,149 +1,505 @@
+use crate::span::Span;
+use std::fmt;
 use std::str;
 
 #[derive(Debug, PartialEq)]
 pub enum Token<'a> {
     LParen,
     RParen,
+    LBrace,
+    RBrace,
     Symbol(&'a str),
     Number(&'a str),
     Literal(&'a str),
+    Bool(bool),
     Quote,
     Dot,
     EOF,
 }
 
+impl fmt::Display for Token<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::LParen => write!(f, "("),
+            Token::RParen => write!(f, ")"),
+            Token::LBrace => write!(f, "{{"),
+            Token::RBrace => write!(f, "}}"),
+            Token::Symbol(v) | Token::Number(v) => write!(f, "{}", v),
+            Token::Literal(v) => write!(f, "\"{}\"", v),
+            Token::Bool(true) => write!(f, "#t"),
+            Token::Bool(false) => write!(f, "#f"),
+            Token::Quote => write!(f, "'"),
+            Token::Dot => write!(f, "."),
+            Token::EOF => write!(f, "end of input"),
+        }
+    }
+}
+
 pub struct Tokenizer2<'a> {
-    input: &'a [u8],
+    input: &'a str,
     pos: usize,
+    // Line bookkeeping, advanced lazily up to the start of each token
+    line: u32,
+    line_start: usize,
+    scanned: usize,
 }
 
-#[derive(Debug, PartialEq)]
-enum ErrorKind {
+#[derive(Debug, PartialEq, Clone)]
+pub enum ErrorKind {
     GeneralError,
+    UnterminatedLiteral,
+    UnterminatedComment,
+    // #; right before a closing paren or the end of input
+    MissingDatum,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct TokenizerError {
-    error: ErrorKind,
+    pub error: ErrorKind,
+    pub span: Span,
+}
+
+impl fmt::Display for TokenizerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.error {
+            ErrorKind::GeneralError => write!(f, "unexpected character"),
+            ErrorKind::UnterminatedLiteral => write!(f, "unterminated string literal"),
+            ErrorKind::UnterminatedComment => write!(f, "unterminated block comment"),
+            ErrorKind::MissingDatum => write!(f, "datum comment without a datum"),
+        }
+    }
 }
 
 impl<'a> Tokenizer2<'a> {
-    pub fn new(input: &str) -> Tokenizer2 {
+    pub fn new(input: &str) -> Tokenizer2<'_> {
         Tokenizer2 {
-            input: input.as_bytes(),
+            input,
             pos: 0,
+            line: 1,
+            line_start: 0,
+            scanned: 0,
         }
     }
 
-    fn get(&mut self) -> Option<u8> {
-        if self.pos < self.input.len() {
-            self.pos += 1;
-            Some(self.input[self.pos - 1])
-        } else {
-            None
+    // Line and column of the byte at `pos`, which must not be behind any
+    // position asked before
+    fn locate(&mut self, pos: usize) -> (u32, u32) {
+        for (i, v) in self.input.as_bytes()[self.scanned..pos].iter().enumerate() {
+            if *v == b'\n' {
+                self.line += 1;
+                self.line_start = self.scanned + i + 1;
+            }
         }
+        self.scanned = pos;
+        let col = self.input[self.line_start..pos].chars().count();
+        (self.line, col as u32 + 1)
     }
 
-    fn put(&mut self) {
-        if self.pos > 0 {
-            self.pos -= 1;
-        }
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn get(&mut self) -> Option<char> {
+        let ret = self.peek()?;
+        self.pos += ret.len_utf8();
+        Some(ret)
     }
 
-//    fn collect_identifier(&mut self) -> Result<&'a [u8], ()> {
-//        let (start, mut end): (usize, usize) = (self.pos - 1, self.pos);
-//        while let Some(v) = self.get() {
-//            match v as char {
-//                'a'..='z' | '+' | '_' | '-' | '&' | '?' | '!' | '0'..='9' => end += 1,
-//                _ => {
-//                    self.put();
-//                    break;
-//                }
-//            }
-//        }
-//        //TODO: return also Errors
-//        //   println!("{}", )
-//        Ok(&self.input[start..end])
-//    }
+    fn span_from(&mut self, start: usize) -> Span {
+        let (line, col) = self.locate(start);
+        Span::new(start, self.pos, line, col)
+    }
 
+    // README grammar plus `?` and `:` (keywords). Any non-ASCII char that
+    // isn't whitespace or a control char is allowed too, so λ, café or →
+    // are fine symbols.
     fn is_character(v: char) -> bool {
-        match v as char {
-            'a'..='z' | '+' | '_' | '-' | '&' | '?' | '!' | '0'..='9' => true,
-            _ => false,
+        match v {
+            'a'..='z' | 'A'..='Z' | '0'..='9' => true,
+            '_' | '+' | '-' | '*' | '/' | '\\' | '=' | '<' | '>' | '!' | '&' | '?' | ':' => true,
+            v => !v.is_ascii() && !v.is_whitespace() && !v.is_control(),
         }
     }
 
+    // Digits, exponents, fractions and ratios: 42, -1.5e3, .5, 1/3
     fn is_number(v: char) -> bool {
-        match v as char {
-            '0'..='9' | '.' => true,
-            _ => false,
+        matches!(v, '0'..='9' | 'e' | 'E' | '.' | '/' | '+' | '-')
+    }
+
+    // A sign starts a number if it is followed by a digit, a dot and a
+    // digit, or one of the special float values +inf.0 and +nan.0
+    fn is_signed_number(&self) -> bool {
+        let rest = &self.input[self.pos..];
+        match rest.chars().next() {
+            Some('0'..='9') => true,
+            Some('.') => self.is_fraction(1),
+            _ => rest.starts_with("inf.0") || rest.starts_with("nan.0"),
         }
     }
 
-    fn collect(&mut self, pred: fn(char) -> bool) -> Result<&'a [u8], ()> {
-        let (start, mut end): (usize, usize) = (self.pos - 1, self.pos);
+    // Whether a digit is `skip` bytes ahead, after a '.'
+    fn is_fraction(&self, skip: usize) -> bool {
+        self.input[self.pos + skip..].starts_with(|c: char| c.is_ascii_digit())
+    }
+
+    // The rest of a number whose first char was already taken. One that
+    // runs into other symbol chars (1x) is taken whole, for the parser to
+    // report as malformed.
+    fn collect_number(&mut self, start: usize) -> &'a str {
+        let rest = &self.input[self.pos..];
+        if rest.starts_with("inf.0") || rest.starts_with("nan.0") {
+            self.pos += 5;
+        } else {
+            self.collect(start, Self::is_number);
+        }
+        if self.peek().is_some_and(Self::is_character) {
+            self.collect(start, Self::is_character);
+        }
+        &self.input[start..self.pos]
+    }
+
+    // Collects the body of a string literal up to the closing quote. Escape
+    // sequences are left untouched here, they are resolved by the parser.
+    fn collect_literal(&mut self) -> Result<&'a str, ()> {
+        let start = self.pos;
         while let Some(v) = self.get() {
-            match pred(v as char) {
-                true => end += 1,
-                false => {
-                    self.put();
-                    break;
-                }
+            match v {
+                '"' => return Ok(&self.input[start..self.pos - 1]),
+                '\\' if self.get().is_none() => break,
+                _ => (),
             }
         }
-        //TODO: return also Errors
-        Ok(&self.input[start..end])
+        Err(())
     }
-}
 
-impl <'a> Iterator for Tokenizer2<'a> {
+    // Collects the rest of a token whose first char was already taken
+    fn collect(&mut self, start: usize, pred: fn(char) -> bool) -> &'a str {
+        while self.peek().is_some_and(pred) {
+            self.get();
+        }
+        &self.input[start..self.pos]
+    }
+
+    // Block comments nest: #| a #| b |# c |#
+    fn skip_block_comment(&mut self) -> Result<(), ()> {
+        let mut depth = 1;
+        while let Some(v) = self.get() {
+            match (v, self.peek()) {
+                ('#', Some('|')) => depth += 1,
+                ('|', Some('#')) => depth -= 1,
+                _ => continue,
+            }
+            self.get();
+            if depth == 0 {
+                return Ok(());
+            }
+        }
+        Err(())
+    }
 
-    type Item = Result<Token<'a>, TokenizerError>;
-
-    fn next(&mut self) -> Option<Result<Token<'a>, TokenizerError>> {
-        if let Some(t) = self.get() {
-            match t as char {
-                ' ' => self.next(),
-                '(' => Some(Ok(Token::LParen)),
-                ')' => Some(Ok(Token::RParen)),
-                'a'..='z' | '+' | '-' | '*' | '/' | '\\' => {
-                    if let Ok(v) = self.collect(Self::is_character) {
-                        Some(Ok(Token::Symbol(str::from_utf8(v).unwrap())))
-                    } else {
-                        Some(Err(TokenizerError{error: ErrorKind::GeneralError}))
-                    }
+    // Skips whitespace and ; and #| |# comments. Returns the start of an
+    // unterminated block comment.
+    fn skip_blank(&mut self) -> Result<(), usize> {
+        loop {
+            let start = self.pos;
+            match self.peek() {
+                Some(v) if v.is_whitespace() => {
+                    self.get();
+                }
+                Some(';') => {
+                    self.collect(start, |v| v != '\n');
                 }
-                '0'..='9' => {
-                    if let Ok(v) = self.collect(Self::is_number) {
-                        Some(Ok(Token::Number(str::from_utf8(v).unwrap())))
-                    } else {
-                        Some(Err(TokenizerError{error: ErrorKind::GeneralError}))
-                    }
+                Some('#') if self.input[self.pos..].starts_with("#|") => {
+                    self.pos += 2;
+                    self.skip_block_comment().map_err(|_| start)?;
                 }
-                '.' => Some(Ok(Token::Dot)),
-                '\'' => Some(Ok(Token::Quote)),
-                _ => Some(Err(TokenizerError{error: ErrorKind::GeneralError})),
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    // Skips one expression after #;, which can be a whole list or
+    // another quoted or commented out expression
+    fn skip_datum(&mut self, at: Span) -> Result<(), TokenizerError> {
+        let missing = TokenizerError {
+            error: ErrorKind::MissingDatum,
+            span: at,
+        };
+        let mut depth = 0;
+        loop {
+            match self.next() {
+                Some(Ok((Token::LParen, _))) | Some(Ok((Token::LBrace, _))) => depth += 1,
+                Some(Ok((Token::RParen, _))) | Some(Ok((Token::RBrace, _))) if depth == 0 => {
+                    return Err(missing)
+                }
+                Some(Ok((Token::RParen, _))) | Some(Ok((Token::RBrace, _))) => depth -= 1,
+                Some(Ok((Token::Quote, _))) => continue,
+                Some(Ok(_)) => (),
+                Some(Err(e)) => return Err(e),
+                None => return Err(missing),
+            }
+            if depth == 0 {
+                return Ok(());
             }
-        } else {
-            None
         }
     }
 }
 
+impl<'a> Tokenizer2<'a> {
+    // `t` is the first char of the token, already taken
+    fn token(&mut self, t: char) -> Result<Token<'a>, ErrorKind> {
+        let start = self.pos - t.len_utf8();
+        match t {
+            '(' => Ok(Token::LParen),
+            ')' => Ok(Token::RParen),
+            '{' => Ok(Token::LBrace),
+            '}' => Ok(Token::RBrace),
+            '+' | '-' if self.is_signed_number() => Ok(Token::Number(self.collect_number(start))),
+            '0'..='9' => Ok(Token::Number(self.collect_number(start))),
+            '.' if self.is_fraction(0) => Ok(Token::Number(self.collect_number(start))),
+            '"' => match self.collect_literal() {
+                Ok(v) => Ok(Token::Literal(v)),
+                Err(_) => Err(ErrorKind::UnterminatedLiteral),
+            },
+            '.' => Ok(Token::Dot),
+            '\'' => Ok(Token::Quote),
+            '#' => match self.collect(self.pos, Self::is_character) {
+                "t" | "true" => Ok(Token::Bool(true)),
+                "f" | "false" => Ok(Token::Bool(false)),
+                _ => Err(ErrorKind::GeneralError),
+            },
+            v if Self::is_character(v) => Ok(Token::Symbol(self.collect(start, Self::is_character))),
+            _ => Err(ErrorKind::GeneralError),
+        }
+    }
+}
+
+impl <'a> Iterator for Tokenizer2<'a> {
+
+    type Item = Result<(Token<'a>, Span), TokenizerError>;
+
+    fn next(&mut self) -> Option<Result<(Token<'a>, Span), TokenizerError>> {
+        if let Err(start) = self.skip_blank() {
+            return Some(Err(TokenizerError {
+                error: ErrorKind::UnterminatedComment,
+                span: self.span_from(start),
+            }));
+        }
+        let start = self.pos;
+        let t = self.get()?;
+        if t == '#' && self.peek() == Some(';') {
+            self.get();
+            let at = self.span_from(start);
+            return match self.skip_datum(at) {
+                Ok(()) => self.next(),
+                Err(e) => Some(Err(e)),
+            };
+        }
+        let (line, col) = self.locate(start);
+        let token = self.token(t);
+        let span = Span::new(start, self.pos, line, col);
+        Some(match token {
+            Ok(v) => Ok((v, span)),
+            Err(error) => Err(TokenizerError { error, span }),
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    fn tokens(input: &str) -> Vec<Token<'_>> {
+        Tokenizer2::new(input).map(|v| v.unwrap().0).collect()
+    }
+
     #[test]
     fn tokenizer_simple_works() {
-        let input = String::from("(+ 2 2)");
-
-        let mut t = Tokenizer2::new(&input);
-        assert_eq!(t.next().unwrap().unwrap(), Token::LParen);
-        assert_eq!(t.next().unwrap().unwrap(), Token::Symbol("+"));
-        assert_eq!(t.next().unwrap().unwrap(), Token::Number("2"));
-        assert_eq!(t.next().unwrap().unwrap(), Token::Number("2"));
-        assert_eq!(t.next().unwrap().unwrap(), Token::RParen);
+        assert_eq!(
+            tokens("(+ 2 2)"),
+            vec![Token::LParen, Token::Symbol("+"), Token::Number("2"), Token::Number("2"), Token::RParen]
+        );
+        let mut t = Tokenizer2::new("");
         assert_eq!(t.next(), None);
         assert_eq!(t.next(), None);
     }
+
+    #[test]
+    fn tokenizer_number_works() {
+        assert_eq!(
+            tokens("(- -1.5e-3 +2 1/3 -inf.0 -x)"),
+            vec![
+                Token::LParen,
+                Token::Symbol("-"),
+                Token::Number("-1.5e-3"),
+                Token::Number("+2"),
+                Token::Number("1/3"),
+                Token::Number("-inf.0"),
+                Token::Symbol("-x"),
+                Token::RParen,
+            ]
+        );
+        assert_eq!(
+            tokens("(+ .5 -.5 1.)"),
+            vec![
+                Token::LParen,
+                Token::Symbol("+"),
+                Token::Number(".5"),
+                Token::Number("-.5"),
+                Token::Number("1."),
+                Token::RParen,
+            ]
+        );
+        assert_eq!(
+            tokens("(a . b) (1 .(2))"),
+            vec![
+                Token::LParen,
+                Token::Symbol("a"),
+                Token::Dot,
+                Token::Symbol("b"),
+                Token::RParen,
+                Token::LParen,
+                Token::Number("1"),
+                Token::Dot,
+                Token::LParen,
+                Token::Number("2"),
+                Token::RParen,
+                Token::RParen,
+            ]
+        );
+        // only number chars, anything else glued on makes it malformed
+        assert_eq!(tokens("1x 2X +inf.0"), vec![Token::Number("1x"), Token::Number("2X"), Token::Number("+inf.0")]);
+        assert_eq!(tokens("-.x"), vec![Token::Symbol("-"), Token::Dot, Token::Symbol("x")]);
+    }
+
+    #[test]
+    fn tokenizer_bool_works() {
+        assert_eq!(
+            tokens("(<= #t #false)"),
+            vec![Token::LParen, Token::Symbol("<="), Token::Bool(true), Token::Bool(false), Token::RParen]
+        );
+        assert!(Tokenizer2::new("#x").next().unwrap().is_err());
+    }
+
+    #[test]
+    fn tokenizer_literal_works() {
+        assert_eq!(
+            tokens(r#"(concat "a \"b\"" "")"#),
+            vec![
+                Token::LParen,
+                Token::Symbol("concat"),
+                Token::Literal(r#"a \"b\""#),
+                Token::Literal(""),
+                Token::RParen,
+            ]
+        );
+
+        let mut t = Tokenizer2::new("\"abc");
+        assert_eq!(
+            t.next().unwrap(),
+            Err(TokenizerError{error: ErrorKind::UnterminatedLiteral, span: Span::new(0, 4, 1, 1)})
+        );
+    }
+
+    fn errors(input: &str) -> Vec<TokenizerError> {
+        Tokenizer2::new(input).filter_map(|v| v.err()).collect()
+    }
+
+    #[test]
+    fn tokenizer_whitespace_works() {
+        let expected = vec![Token::LParen, Token::Symbol("a"), Token::Number("1"), Token::RParen];
+        for blank in [" ", "\t", "\n", "\r\n", "\x0b", "\x0c", "\u{a0}", "\u{2003}", "\u{2028}", "\u{3000}"] {
+            let input = format!("{0}({0}a{0}1{0}){0}", blank);
+            assert_eq!(tokens(&input), expected, "{:?}", blank);
+        }
+        assert_eq!(tokens("(a 1)\n"), expected);
+        assert_eq!(tokens(" \t\r\n"), vec![]);
+    }
+
+    #[test]
+    fn tokenizer_comments_works() {
+        let expected = vec![Token::LParen, Token::Symbol("a"), Token::Number("1"), Token::RParen];
+        for input in [
+            "; leading\n(a 1)",
+            "(a ; inside (\n 1) ; trailing",
+            "(a 1);no newline at the end",
+            ";;; (b)\n(a 1)",
+            "#| block |#(a 1)",
+            "(a #|\n spans \"lines\" |# 1)",
+            "(a #| outer #| nested |# still a comment |# 1)",
+            "(a 1)#||#",
+            "(a #; b 1)",
+            "(a #;(b (c) {d}) 1)",
+            "(a #;'(b c) 1)",
+            "(a #; #;b c 1)",
+            "(a #;#|x|#b 1)",
+            "#;(a 2)\n(a 1)",
+            "(a #;\"b)\" 1)",
+        ] {
+            assert_eq!(tokens(input), expected, "{:?}", input);
+        }
+        // comment markers mean nothing in strings
+        assert_eq!(tokens("\"; #| #;\""), vec![Token::Literal("; #| #;")]);
+
+        assert_eq!(
+            errors("(a #| x #| y |#\n)"),
+            vec![TokenizerError {
+                error: ErrorKind::UnterminatedComment,
+                span: Span::new(3, 17, 1, 4)
+            }]
+        );
+        let missing = |start, col| TokenizerError {
+            error: ErrorKind::MissingDatum,
+            span: Span::new(start, start + 2, 1, col),
+        };
+        assert_eq!(errors("(a #;)"), vec![missing(3, 4)]);
+        assert_eq!(errors("(a #; )"), vec![missing(3, 4)]);
+        assert_eq!(errors("#;"), vec![missing(0, 1)]);
+        assert_eq!(errors("#; ;c"), vec![missing(0, 1)]);
+        assert_eq!(errors("#;'"), vec![missing(0, 1)]);
+        assert_eq!(errors("#;(a"), vec![missing(0, 1)]);
+    }
+
+    #[test]
+    fn tokenizer_symbol_works() {
+        for sym in [
+            "a", "Foo", "FOO-BAR", "x1", "_x", "set-car!", "pair?", "&rest", ":key", "a*b", "x/y", "a\\b",
+            "<=>", "!=", "*", "/", "\\", "-", "+", "->", "λ", "café", "Ärger", "→", "変数", "x²",
+        ] {
+            assert_eq!(tokens(sym), vec![Token::Symbol(sym)], "{:?}", sym);
+        }
+        assert_eq!(
+            tokens("(λx FOO)"),
+            vec![Token::LParen, Token::Symbol("λx"), Token::Symbol("FOO"), Token::RParen]
+        );
+        // a dot ends a symbol
+        assert_eq!(tokens("a.b"), vec![Token::Symbol("a"), Token::Dot, Token::Symbol("b")]);
+        for bad in ["@", "%", "|", "[", "#x", "#", "\u{7}"] {
+            let e = errors(bad);
+            assert_eq!(e.len(), 1, "{:?}", bad);
+            assert_eq!(e[0].error, ErrorKind::GeneralError, "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn tokenizer_spans_works() {
+        let spans: Vec<Span> = Tokenizer2::new("(foo\n  \"λ\" @)\n\"a\nb\"")
+            .map(|v| match v {
+                Ok((_, span)) => span,
+                Err(e) => e.span,
+            })
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                Span::new(0, 1, 1, 1),
+                Span::new(1, 4, 1, 2),
+                // λ is two bytes but one column
+                Span::new(7, 11, 2, 3),
+                Span::new(12, 13, 2, 7),
+                Span::new(13, 14, 2, 8),
+                Span::new(15, 20, 3, 1),
+            ]
+        );
+    }
 }